@@ -1,6 +1,6 @@
-use dcpu16::{assemble, Register, DCPU16};
+use dcpu16::{assemble, AssembleError, Register, DCPU16};
 
-fn main() {
+fn main() -> Result<(), AssembleError> {
     // Use the RUST_LOG environment variable to configure, e.g. RUST_LOG=dcpu16=trace
     tracing_subscriber::fmt::init();
 
@@ -48,7 +48,7 @@ fn main() {
         :crash        SET PC, crash            ; 7dc1 001a
     ";
 
-    let program = assemble(&source);
+    let program = assemble(source)?;
 
     let mut cpu = DCPU16::new(program.as_slice());
     println!("{}", cpu.hexdump_program(8));
@@ -70,4 +70,5 @@ fn main() {
 
     // Print the RAM contents.
     println!("{}", cpu.hexdump_ram(32));
+    Ok(())
 }
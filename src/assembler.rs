@@ -1,92 +1,687 @@
 use crate::instruction_argument::{InstructionArgument, SpecialRegister, StackOperation};
+use crate::instruction_word::EXTENDED_PREFIX;
+use crate::log::trace;
 use crate::{Register, Word};
+use pest::error::LineColLocation;
 use pest::iterators::Pair;
-use pest::Parser;
+use pest::{Parser, Span};
 use pest_derive::Parser;
-use std::collections::HashMap;
-use tracing::trace;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
 
 #[derive(Parser)]
 #[grammar = "assemble.pest"]
 struct AssembleParser;
 
+/// An error that occurred while assembling a program.
+///
+/// Line and column numbers are one-based.
+#[derive(Debug)]
+pub enum AssembleError {
+    /// The source code could not be parsed.
+    Parse {
+        error: Box<pest::error::Error<Rule>>,
+        line: usize,
+        column: usize,
+    },
+    /// A label or constant was defined multiple times.
+    DuplicateLabel {
+        label: String,
+        line: usize,
+        column: usize,
+    },
+    /// A label or constant was referenced, but never defined.
+    UndefinedLabel {
+        label: String,
+        line: usize,
+        column: usize,
+    },
+    /// A decimal literal is outside of the signed and unsigned 16 bit range.
+    LiteralOutOfRange {
+        literal: String,
+        line: usize,
+        column: usize,
+    },
+    /// An origin directive points before code that was already placed.
+    OriginOverlap {
+        address: Word,
+        line: usize,
+        column: usize,
+    },
+    /// The count of a `DUP` modifier is zero or negative.
+    InvalidDupCount {
+        count: String,
+        line: usize,
+        column: usize,
+    },
+    /// The program exceeds the addressable memory.
+    MemoryOverflow { line: usize, column: usize },
+    /// An expression divides by zero.
+    DivisionByZero { line: usize, column: usize },
+    /// An expression, or a part of it, does not fit into an unsigned 16 bit word.
+    Overflow { line: usize, column: usize },
+    /// The location counter was used in a constant, which has no address.
+    LocationInConstant { line: usize, column: usize },
+    /// An included source could not be resolved.
+    UnresolvedInclude {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+    /// A source includes itself, directly or through other included sources.
+    IncludeCycle {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+    /// A single instruction was expected, but none, several, a label or a directive was found.
+    NotSingleInstruction { line: usize, column: usize },
+    /// Several independent errors occurred, ordered by their position in the source code.
+    Multiple(Vec<AssembleError>),
+}
+
+impl AssembleError {
+    /// Gets the line in the source code at which the error occurred.
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Parse { line, .. } => *line,
+            Self::DuplicateLabel { line, .. } => *line,
+            Self::UndefinedLabel { line, .. } => *line,
+            Self::LiteralOutOfRange { line, .. } => *line,
+            Self::OriginOverlap { line, .. } => *line,
+            Self::InvalidDupCount { line, .. } => *line,
+            Self::MemoryOverflow { line, .. } => *line,
+            Self::DivisionByZero { line, .. } => *line,
+            Self::Overflow { line, .. } => *line,
+            Self::LocationInConstant { line, .. } => *line,
+            Self::UnresolvedInclude { line, .. } => *line,
+            Self::IncludeCycle { line, .. } => *line,
+            Self::NotSingleInstruction { line, .. } => *line,
+            Self::Multiple(errors) => errors[0].line(),
+        }
+    }
+
+    /// Gets the column in the source code at which the error occurred.
+    pub fn column(&self) -> usize {
+        match self {
+            Self::Parse { column, .. } => *column,
+            Self::DuplicateLabel { column, .. } => *column,
+            Self::UndefinedLabel { column, .. } => *column,
+            Self::LiteralOutOfRange { column, .. } => *column,
+            Self::OriginOverlap { column, .. } => *column,
+            Self::InvalidDupCount { column, .. } => *column,
+            Self::MemoryOverflow { column, .. } => *column,
+            Self::DivisionByZero { column, .. } => *column,
+            Self::Overflow { column, .. } => *column,
+            Self::LocationInConstant { column, .. } => *column,
+            Self::UnresolvedInclude { column, .. } => *column,
+            Self::IncludeCycle { column, .. } => *column,
+            Self::NotSingleInstruction { column, .. } => *column,
+            Self::Multiple(errors) => errors[0].column(),
+        }
+    }
+
+    /// Gets all errors that occurred, e.g. to report them individually.
+    pub fn errors(&self) -> &[AssembleError] {
+        match self {
+            Self::Multiple(errors) => errors,
+            _ => std::slice::from_ref(self),
+        }
+    }
+
+    /// Splits the error into the errors that occurred.
+    fn into_errors(self) -> Vec<AssembleError> {
+        match self {
+            Self::Multiple(errors) => errors,
+            error => vec![error],
+        }
+    }
+
+    /// Combines the errors, if any, into a single error.
+    fn combine(mut errors: Vec<AssembleError>) -> Result<(), Self> {
+        errors.sort_by_key(|error| (error.line(), error.column()));
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Self::Multiple(errors)),
+        }
+    }
+}
+
+impl Display for AssembleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Self::Multiple(errors) = self {
+            for (i, error) in errors.iter().enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write!(f, "{}", error)?;
+            }
+            return Ok(());
+        }
+
+        write!(f, "line {}, column {}: ", self.line(), self.column())?;
+        match self {
+            Self::Parse { error, .. } => write!(f, "unsuccessful parse: {}", error),
+            Self::DuplicateLabel { label, .. } => {
+                write!(f, "Label '{}' defined multiple times", label)
+            }
+            Self::UndefinedLabel { label, .. } => write!(f, "Label '{}' is not defined", label),
+            Self::LiteralOutOfRange { literal, .. } => {
+                write!(f, "Literal '{}' does not fit into 16 bits", literal)
+            }
+            Self::OriginOverlap { address, .. } => {
+                write!(f, "Origin 0x{:04X} overlaps preceding code", address)
+            }
+            Self::InvalidDupCount { count, .. } => {
+                write!(f, "DUP count '{}' must be at least 1", count)
+            }
+            Self::MemoryOverflow { .. } => write!(f, "Program exceeds the addressable memory"),
+            Self::DivisionByZero { .. } => write!(f, "Division by zero in expression"),
+            Self::Overflow { .. } => write!(f, "Expression does not fit into 16 bits"),
+            Self::LocationInConstant { .. } => {
+                write!(f, "The location counter cannot be used in constants")
+            }
+            Self::UnresolvedInclude { name, .. } => write!(f, "Include '{}' not found", name),
+            Self::IncludeCycle { name, .. } => write!(f, "Include '{}' includes itself", name),
+            Self::NotSingleInstruction { .. } => {
+                write!(f, "Expected a single instruction without labels")
+            }
+            Self::Multiple(..) => unreachable!(),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<pest::error::Error<Rule>> for AssembleError {
+    fn from(error: pest::error::Error<Rule>) -> Self {
+        let (line, column) = match error.line_col {
+            LineColLocation::Pos(position) => position,
+            LineColLocation::Span(start, _) => start,
+        };
+        Self::Parse {
+            error: Box::new(error),
+            line,
+            column,
+        }
+    }
+}
+
+/// A note about an optimization applied while assembling a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssembleNote {
+    /// A literal computed from labels or constants fits into the instruction word,
+    /// saving the next word.
+    InlinedLiteral {
+        /// The value of the literal.
+        value: Word,
+        /// The address of the instruction.
+        address: Word,
+    },
+}
+
+impl Display for AssembleNote {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InlinedLiteral { value, address } => {
+                write!(f, "literal 0x{:02X} at word {} was inlined", value, address)
+            }
+        }
+    }
+}
+
+/// The location of a label definition or reference in the source code.
+#[derive(Debug, Clone)]
+struct LabelLocation {
+    label: String,
+    line: usize,
+    column: usize,
+}
+
+impl LabelLocation {
+    fn new(label: &str, span: Span) -> Self {
+        let (line, column) = span.start_pos().line_col();
+        Self {
+            label: String::from(label),
+            line,
+            column,
+        }
+    }
+}
+
+/// The locations of all label definitions and references, used for error reporting.
+#[derive(Debug, Default)]
+struct LabelLocations {
+    definitions: Vec<LabelLocation>,
+    references: Vec<LabelLocation>,
+    /// The most recent global label, which local labels are scoped to.
+    scope: String,
+}
+
+impl LabelLocations {
+    /// Records the definition of a label or constant and returns its qualified name.
+    fn define(&mut self, pair: &Pair<Rule>) -> String {
+        let label = qualify_label(pair.as_str(), &self.scope);
+        self.definitions
+            .push(LabelLocation::new(&label, pair.as_span()));
+        label
+    }
+
+    /// Records the label references of an operand, including those nested in expressions.
+    fn record_references(&mut self, pair: &Pair<Rule>) {
+        for inner in pair.clone().into_inner().flatten() {
+            if inner.as_rule() == Rule::label_ref {
+                let label = qualify_label(inner.as_str(), &self.scope);
+                self.references
+                    .push(LabelLocation::new(&label, inner.as_span()));
+            }
+        }
+    }
+}
+
+/// The name under which the location counter, i.e. `$` or `.`, is tracked in the label map.
+/// It cannot collide with a label name.
+const LOCATION_COUNTER: &str = "$";
+
+/// Qualifies a local label, i.e. a label starting with a dot, with the name of
+/// the global label it is scoped to, e.g. `.loop` becomes `copy.loop`.
+fn qualify_label(label: &str, scope: &str) -> String {
+    if label.starts_with('.') {
+        format!("{}{}", scope, label)
+    } else {
+        String::from(label)
+    }
+}
+
 /// Assembles the source code into an DCPU-16 program bytecode.
-pub fn assemble<T>(source: T) -> Vec<Word>
+pub fn assemble<T>(source: T) -> Result<Vec<Word>, AssembleError>
+where
+    T: AsRef<str>,
+{
+    assemble_with_symbols(source).map(|(program, _)| program)
+}
+
+/// Assembles the source code into an DCPU-16 program bytecode, resolving the sources of
+/// `.include "name"` directives through the resolver, e.g. to read them from files.
+///
+/// The resolver returns the source code of an included name, or `None` if it does not exist.
+/// Included sources are assembled in place of the directive and may include other sources.
+pub fn assemble_with_resolver<T, F>(source: T, resolver: F) -> Result<Vec<Word>, AssembleError>
+where
+    T: AsRef<str>,
+    F: FnMut(&str) -> Option<String>,
+{
+    assemble_program(&[source], resolver).map(|assembled| assembled.program)
+}
+
+/// Assembles multiple sources, e.g. a program and a library, into a single DCPU-16
+/// program bytecode.
+///
+/// The sources are placed one after another and share their labels, i.e. each source can
+/// refer to the labels of all others. Labels defined in multiple sources are reported as
+/// duplicates; local labels are scoped to their source.
+pub fn assemble_units(units: &[&str]) -> Result<Vec<Word>, AssembleError> {
+    assemble_program(units, no_includes).map(|assembled| assembled.program)
+}
+
+/// Computes the number of words the source code assembles to, without generating the
+/// program bytecode, e.g. to display the program size while editing.
+///
+/// Errors in the expressions of instructions and data are only reported when the program
+/// is generated, e.g. by [`assemble`].
+pub fn assembled_size<T: AsRef<str>>(source: T) -> Result<usize, AssembleError> {
+    layout_program(&[source], no_includes).map(|layout| layout.size)
+}
+
+/// Assembles a single instruction, e.g. `SET A, 0x30`, into its one to three words.
+///
+/// Labels, directives and further instructions are rejected; this is intended for
+/// interactive use and for testing encodings.
+pub fn assemble_line(line: &str) -> Result<Vec<Word>, AssembleError> {
+    let mut tokens = Vec::new();
+    let mut label_locations = LabelLocations::default();
+    get_meta_instructions(
+        line,
+        &mut no_includes,
+        &mut Vec::new(),
+        &mut tokens,
+        &mut label_locations,
+    )?;
+
+    if let Some(definition) = label_locations.definitions.first() {
+        return Err(AssembleError::NotSingleInstruction {
+            line: definition.line,
+            column: definition.column,
+        });
+    }
+
+    match tokens.as_slice() {
+        [Token {
+            meta_instruction: MetaInstruction::Instruction(_),
+            ..
+        }] => assemble(line),
+        _ => Err(AssembleError::NotSingleInstruction { line: 1, column: 1 }),
+    }
+}
+
+/// Assembles the source code into an DCPU-16 program bytecode, along with notes about
+/// the optimizations applied, e.g. for code size feedback.
+///
+/// Literals computed from labels or constants are inlined into the instruction word whenever
+/// their final value permits, since the sizes of the instructions are refined until all
+/// labels are stable. The notes report each of these literals.
+pub fn assemble_with_notes<T>(source: T) -> Result<(Vec<Word>, Vec<AssembleNote>), AssembleError>
+where
+    T: AsRef<str>,
+{
+    assemble_program(&[source], no_includes).map(|assembled| (assembled.program, assembled.notes))
+}
+
+/// Assembles the source code into an DCPU-16 program bytecode, along with
+/// the final word addresses of all labels.
+///
+/// Local labels are named after their enclosing global label, e.g. `copy.loop`.
+pub fn assemble_with_symbols<T>(
+    source: T,
+) -> Result<(Vec<Word>, HashMap<String, Word>), AssembleError>
+where
+    T: AsRef<str>,
+{
+    assemble_program(&[source], no_includes).map(|assembled| (assembled.program, assembled.symbols))
+}
+
+/// A resolver for sources that do not include other sources.
+fn no_includes(_name: &str) -> Option<String> {
+    None
+}
+
+/// An assembled program.
+struct Assembled {
+    /// The program bytecode.
+    program: Vec<Word>,
+    /// The final word addresses of all labels.
+    symbols: HashMap<String, Word>,
+    /// Notes about the optimizations applied.
+    notes: Vec<AssembleNote>,
+}
+
+/// The instructions of a program at their final positions, ready to be written.
+struct Layout {
+    /// The instructions in the order of the source code.
+    instructions: Vec<MaterializedInstruction>,
+    /// The final word addresses of all labels and the values of all constants.
+    label_map: HashMap<String, Word>,
+    /// The names of the constants in the label map.
+    constant_names: HashSet<String>,
+    /// The number of words of the program.
+    size: usize,
+}
+
+/// Assembles the sources into the program bytecode, the label addresses and the
+/// optimization notes. The sources are placed one after another.
+fn assemble_program<T, F>(sources: &[T], resolver: F) -> Result<Assembled, AssembleError>
 where
     T: AsRef<str>,
+    F: FnMut(&str) -> Option<String>,
 {
-    let tokens = get_meta_instructions(source);
+    let Layout {
+        instructions,
+        mut label_map,
+        constant_names,
+        size,
+    } = layout_program(sources, resolver)?;
 
+    // Go through the instructions one last time and generate the byte stream.
+    let mut bytesteam = Vec::with_capacity(size);
+    let mut notes = Vec::new();
+    for entry in instructions {
+        write_materialized_instruction_into_bytestream(
+            &mut bytesteam,
+            entry,
+            &mut label_map,
+            &mut notes,
+        )?;
+    }
+
+    label_map.retain(|name, _| name != LOCATION_COUNTER && !constant_names.contains(name));
+    Ok(Assembled {
+        program: bytesteam,
+        symbols: label_map,
+        notes,
+    })
+}
+
+/// Parses the sources and places their instructions, i.e. runs all passes except
+/// for generating the byte stream.
+fn layout_program<T, F>(sources: &[T], mut resolver: F) -> Result<Layout, AssembleError>
+where
+    T: AsRef<str>,
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut errors = Vec::new();
+    let mut tokens = Vec::new();
+    let mut label_locations = LabelLocations::default();
+    for source in sources {
+        // Local labels are scoped to their source.
+        label_locations.scope.clear();
+        if let Err(error) = get_meta_instructions(
+            source,
+            &mut resolver,
+            &mut Vec::new(),
+            &mut tokens,
+            &mut label_locations,
+        ) {
+            errors.extend(error.into_errors());
+        }
+    }
+    AssembleError::combine(errors)?;
+
+    let mut errors = Vec::new();
     let mut label_map = HashMap::new();
-    for token in tokens.iter() {
-        if let MetaInstruction::Label(label) = token {
-            if label_map.insert(label.clone(), 0x0000u16).is_some() {
-                panic!("Label '{}' defined multiple times", label);
-            }
+    for definition in label_locations.definitions {
+        if label_map
+            .insert(definition.label.clone(), 0x0000u16)
+            .is_some()
+        {
+            errors.push(AssembleError::DuplicateLabel {
+                label: definition.label,
+                line: definition.line,
+                column: definition.column,
+            });
+        }
+    }
+
+    for reference in label_locations.references {
+        if !label_map.contains_key(&reference.label) {
+            errors.push(AssembleError::UndefinedLabel {
+                label: reference.label,
+                line: reference.line,
+                column: reference.column,
+            });
         }
     }
+    AssembleError::combine(errors)?;
+
+    // Constants may be used before their definition, hence they are resolved up front.
+    // Since they may refer to labels, they are estimated again whenever labels move.
+    let constants: Vec<(String, Expression)> = tokens
+        .iter()
+        .filter_map(|token| match &token.meta_instruction {
+            MetaInstruction::Constant(name, expression) => Some((name.clone(), expression.clone())),
+            _ => None,
+        })
+        .collect();
+    let constant_names: HashSet<String> = constants.iter().map(|(name, _)| name.clone()).collect();
+    estimate_constants(&constants, &mut label_map);
 
     let mut instructions = Vec::new();
+    let mut locations = Vec::new();
     let mut current_position: Word = 0x0000;
 
     // First pass, materialize as many instructions as possible.
     for token in tokens {
-        match token {
+        let location = (token.line, token.column);
+        let memory_overflow = AssembleError::MemoryOverflow {
+            line: token.line,
+            column: token.column,
+        };
+        match token.meta_instruction {
             MetaInstruction::Instruction(instruction) => {
-                let materialized = instruction.materialize(&label_map);
+                label_map.insert(String::from(LOCATION_COUNTER), current_position);
+                let materialized = instruction.materialize(&label_map, current_position);
 
                 // We assume the best-case situation here.
                 // This is helpful because small values can be inlined
                 // into the instruction.
                 let len = materialized.len_estimate();
-                current_position += len as Word;
+                current_position = current_position
+                    .checked_add(len as Word)
+                    .ok_or(memory_overflow)?;
 
                 instructions.push(materialized);
             }
+            MetaInstruction::Data(words) => {
+                current_position = Word::try_from(words.len())
+                    .ok()
+                    .and_then(|len| current_position.checked_add(len))
+                    .ok_or(memory_overflow)?;
+                instructions.push(MaterializedInstruction::Data { words });
+            }
+            MetaInstruction::Reserve(count) => {
+                current_position = current_position.checked_add(count).ok_or(memory_overflow)?;
+                instructions.push(MaterializedInstruction::Reserve { count });
+            }
             MetaInstruction::Label(label) => {
                 label_map.insert(label.clone(), current_position);
+                instructions.push(MaterializedInstruction::Label { label });
+            }
+            MetaInstruction::Align(alignment) => {
+                let padding = alignment_padding(current_position, alignment);
+                current_position = current_position
+                    .checked_add(padding)
+                    .ok_or(memory_overflow)?;
+                instructions.push(MaterializedInstruction::Align { alignment, padding });
+            }
+            MetaInstruction::Constant(..) => continue,
+            MetaInstruction::Origin {
+                address,
+                line,
+                column,
+            } => {
+                if address < current_position {
+                    return Err(AssembleError::OriginOverlap {
+                        address,
+                        line,
+                        column,
+                    });
+                }
+                current_position = address;
+                instructions.push(MaterializedInstruction::Origin {
+                    address,
+                    line,
+                    column,
+                });
             }
         }
+        locations.push(location);
     }
 
     // Second pass, attempt to materialize the "flexible" instructions.
     let mut current_position: Word;
     loop {
         let mut replace_list = Vec::new();
+        let mut labels_moved = false;
         current_position = 0x0000;
+        estimate_constants(&constants, &mut label_map);
 
         for (i, entry) in instructions.iter().enumerate() {
+            let (line, column) = locations[i];
+            let memory_overflow = || AssembleError::MemoryOverflow { line, column };
+
             let current_length = entry.len_estimate();
-            current_position += current_length as Word;
+            let start = current_position;
+            current_position = start
+                .checked_add(current_length as Word)
+                .ok_or_else(memory_overflow)?;
 
-            match entry {
+            let new_instruction = match entry {
                 MaterializedInstruction::Static { .. } => continue,
+                MaterializedInstruction::Data { .. } => continue,
+                MaterializedInstruction::Reserve { .. } => continue,
+                MaterializedInstruction::Origin { address, .. } => {
+                    current_position = *address;
+                    continue;
+                }
+                MaterializedInstruction::Label { label } => {
+                    // Labels preceding a change in padding may have been moved along
+                    // with the following code, hence they are placed again.
+                    if label_map.insert(label.clone(), start) != Some(start) {
+                        labels_moved = true;
+                    }
+                    continue;
+                }
+                MaterializedInstruction::Align { alignment, .. } => {
+                    MaterializedInstruction::Align {
+                        alignment: *alignment,
+                        padding: alignment_padding(start, *alignment),
+                    }
+                }
                 MaterializedInstruction::Flexible { instruction, .. } => {
-                    let new_instruction = instruction.materialize(&label_map);
-                    let new_length = new_instruction.len_estimate();
-
-                    let difference = new_length as i64 - current_length as i64;
-                    // assert!(difference >= 0);
-
-                    // If the instruction changed in size, we need to adjust all following
-                    // label positions.
-                    if difference != 0 {
-                        // Store the updated entry for later replacement in the list.
-                        replace_list.push((i, new_instruction));
-
-                        // Update the labels.
-                        for (_label, label_pos) in label_map.iter_mut() {
-                            if *label_pos > current_position {
-                                *label_pos = (*label_pos as i64 + difference) as Word;
-                            }
-                        }
+                    label_map.insert(String::from(LOCATION_COUNTER), start);
+                    let mut new_instruction = instruction.materialize(&label_map, start);
+
+                    // Jumps that outgrew their relative form stay absolute from now on,
+                    // so that the sizes eventually settle.
+                    if new_instruction.len_estimate() > current_length {
+                        new_instruction = instruction.expanded().materialize(&label_map, start);
                     }
+                    new_instruction
                 }
+            };
+            let new_length = new_instruction.len_estimate();
+
+            let difference = new_length as i64 - current_length as i64;
+
+            // If the instruction changed in size, we need to adjust all following
+            // label positions.
+            if difference != 0 {
+                // Store the updated entry for later replacement in the list.
+                replace_list.push((i, new_instruction));
+
+                // Update the labels up to the next origin, which stays in place.
+                let next_origin = instructions[i + 1..]
+                    .iter()
+                    .find_map(|entry| match entry {
+                        MaterializedInstruction::Origin { address, .. } => Some(*address),
+                        _ => None,
+                    })
+                    .map_or(usize::MAX, |address| address as usize);
+                for (label, label_pos) in label_map.iter_mut() {
+                    if *label_pos >= current_position
+                        && (*label_pos as usize) < next_origin
+                        && !constant_names.contains(label)
+                    {
+                        *label_pos = (*label_pos as i64 + difference) as Word;
+                    }
+                }
+
+                // The following instructions move as well.
+                current_position = Word::try_from(current_position as i64 + difference)
+                    .map_err(|_| memory_overflow())?;
             }
         }
 
         // If no instruction was replaced we arrived at a local optimum.
-        if replace_list.len() == 0 {
+        if replace_list.is_empty() && !labels_moved {
             break;
         }
 
@@ -96,26 +691,50 @@ where
         }
     }
 
-    // Go through the instructions one last time and generate the byte stream.
-    let mut bytesteam = Vec::with_capacity(current_position as usize);
-    for entry in instructions {
-        write_materialized_instruction_into_bytestream(&mut bytesteam, entry, &mut label_map)
+    // Now that all labels are final, report errors in the constants.
+    for (name, expression) in constants.iter() {
+        let value = expression.evaluate(&label_map)?;
+        label_map.insert(name.clone(), value);
+    }
+
+    Ok(Layout {
+        instructions,
+        label_map,
+        constant_names,
+        size: current_position as usize,
+    })
+}
+
+/// Estimates the values of the constants in the order of their definition.
+fn estimate_constants(constants: &[(String, Expression)], label_map: &mut HashMap<String, Word>) {
+    for (name, expression) in constants {
+        let value = expression.estimate(label_map);
+        label_map.insert(name.clone(), value);
     }
+}
 
-    bytesteam
+/// Gets the number of zero words required to move the position to the next multiple
+/// of the alignment. Alignments of zero and one never require padding.
+fn alignment_padding(position: Word, alignment: Word) -> Word {
+    if alignment <= 1 {
+        return 0;
+    }
+    (alignment - position % alignment) % alignment
 }
 
 /// Writes a materialized instruction into the bytestream.
 /// A final pass of jump label address substitution is performed.
 fn write_materialized_instruction_into_bytestream(
-    mut bytesteam: &mut Vec<u16>,
+    bytesteam: &mut Vec<u16>,
     entry: MaterializedInstruction,
     label_map: &mut HashMap<String, u16>,
-) {
+    notes: &mut Vec<AssembleNote>,
+) -> Result<(), AssembleError> {
     let length = entry.len_estimate();
     match entry {
         MaterializedInstruction::Static {
             instruction,
+            prefix,
             instruction_word,
             arg1,
             arg2,
@@ -125,63 +744,157 @@ fn write_materialized_instruction_into_bytestream(
                 instruction = instruction,
                 words = length
             );
-            write_instruction_word_into_bytestream(&mut bytesteam, instruction_word, arg1, arg2)
+            write_instruction_word_into_bytestream(bytesteam, prefix, instruction_word, arg1, arg2)
         }
         MaterializedInstruction::Flexible { instruction, .. } => {
             // We perform a final pass of baking the actual jump addresses
-            // into the instructions.
+            // into the instructions. Now that all labels are final, errors
+            // in the expressions are reported.
+            let position = bytesteam.len() as Word;
+            label_map.insert(String::from(LOCATION_COUNTER), position);
+            if let Some(value) = instruction.evaluate_expressions(label_map, position)? {
+                if value <= 0x1f {
+                    notes.push(AssembleNote::InlinedLiteral {
+                        value,
+                        address: bytesteam.len() as Word,
+                    });
+                }
+            }
             if let MaterializedInstruction::Flexible {
                 instruction,
+                prefix,
                 instruction_word,
                 arg1,
                 arg2,
-            } = instruction.materialize(&label_map)
+            } = instruction.materialize(label_map, position)
             {
                 trace!(
                     "instruction {instruction:?}, len = {words}",
                     instruction = instruction,
                     words = length
                 );
-                write_instruction_word_into_bytestream(&mut bytesteam, instruction_word, arg1, arg2)
+                write_instruction_word_into_bytestream(
+                    bytesteam,
+                    prefix,
+                    instruction_word,
+                    arg1,
+                    arg2,
+                )
             } else {
                 unreachable!();
             }
         }
+        MaterializedInstruction::Data { words } => {
+            trace!("data, len = {words}", words = length);
+            for word in words {
+                label_map.insert(String::from(LOCATION_COUNTER), bytesteam.len() as Word);
+                bytesteam.push(word.evaluate(label_map)?);
+            }
+        }
+        MaterializedInstruction::Reserve { count } => {
+            trace!("reserve, len = {words}", words = count);
+            bytesteam.resize(bytesteam.len() + count as usize, 0x0000);
+        }
+        MaterializedInstruction::Label { .. } => {}
+        MaterializedInstruction::Align { alignment, .. } => {
+            let padding = alignment_padding(bytesteam.len() as Word, alignment);
+            trace!(
+                "align {alignment}, len = {words}",
+                alignment = alignment,
+                words = padding
+            );
+            bytesteam.resize(bytesteam.len() + padding as usize, 0x0000);
+        }
+        MaterializedInstruction::Origin {
+            address,
+            line,
+            column,
+        } => {
+            // Instructions may have grown into the origin after the first pass.
+            if bytesteam.len() > address as usize {
+                return Err(AssembleError::OriginOverlap {
+                    address,
+                    line,
+                    column,
+                });
+            }
+            trace!("origin {address:04X}", address = address);
+            bytesteam.resize(address as usize, 0x0000);
+        }
     }
+
+    Ok(())
 }
 
 /// Writes an individual instruction word into the bytestream.
 fn write_instruction_word_into_bytestream(
     bytesteam: &mut Vec<u16>,
+    prefix: Option<u16>,
     instruction_word: u16,
     arg1: Option<u16>,
     arg2: Option<u16>,
 ) {
+    if let Some(prefix) = prefix {
+        bytesteam.push(prefix);
+    }
     bytesteam.push(instruction_word);
-    if arg1.is_some() {
-        bytesteam.push(arg1.unwrap());
+    if let Some(arg1) = arg1 {
+        bytesteam.push(arg1);
     }
-    if arg2.is_some() {
-        bytesteam.push(arg2.unwrap());
+    if let Some(arg2) = arg2 {
+        bytesteam.push(arg2);
     }
 }
 
-/// Parses the source and generates a stream of [`MetaInstruction`] instances.
-fn get_meta_instructions<T>(source: T) -> Vec<MetaInstruction>
+/// Parses the source and generates a stream of [`MetaInstruction`] instances,
+/// along with the locations of all labels.
+///
+/// Lines that fail to parse are skipped so that all of them are reported at once.
+/// Included sources are resolved and parsed in place; `includes` holds the names
+/// of the sources currently being included, to detect cycles.
+fn get_meta_instructions<T, F>(
+    source: T,
+    resolver: &mut F,
+    includes: &mut Vec<String>,
+    meta_instructions: &mut Vec<Token>,
+    label_locations: &mut LabelLocations,
+) -> Result<(), AssembleError>
 where
     T: AsRef<str>,
+    F: FnMut(&str) -> Option<String>,
 {
+    let mut errors = Vec::new();
+    let mut source = String::from(source.as_ref());
+
     // Get the top-level program rule.
-    let mut program =
-        AssembleParser::parse(Rule::program, source.as_ref()).expect("unsuccessful parse");
-    let program = program.next().unwrap();
+    let program = loop {
+        match AssembleParser::parse(Rule::program, &source) {
+            Ok(mut program) => break program.next().unwrap(),
+            Err(error) => {
+                let error = AssembleError::from(error);
+                let line = error.line();
+                errors.push(error);
+                if !blank_line(&mut source, line) {
+                    return Err(AssembleError::combine(errors).unwrap_err());
+                }
+            }
+        }
+    };
 
-    let mut meta_instructions = Vec::new();
     for record in program.into_inner() {
+        if let Err(error) = check_literals(&record) {
+            errors.push(error);
+            continue;
+        }
+
+        let (line, column) = record.as_span().start_pos().line_col();
         let token = match record.as_rule() {
             Rule::label => {
-                let inner = record.into_inner();
-                MetaInstruction::Label(String::from(inner.as_str()))
+                let name = record.into_inner().next().unwrap();
+                if !name.as_str().starts_with('.') {
+                    label_locations.scope = String::from(name.as_str());
+                }
+                MetaInstruction::Label(label_locations.define(&name))
             }
             Rule::basic_instruction => {
                 let mut instruction = record.into_inner();
@@ -190,6 +903,8 @@ where
                 let a = instruction.next().unwrap();
                 let b = instruction.next().unwrap();
 
+                label_locations.record_references(&b);
+
                 let operation = parse_basic_operation(op);
                 let value_a = parse_instruction_argument(a);
                 let value_b = parse_value(b);
@@ -204,12 +919,143 @@ where
                 let a = instruction.next().unwrap();
                 assert!(instruction.next().is_none());
 
+                label_locations.record_references(&a);
+
                 let operation = parse_nonbasic_operation(op);
                 let value_a = parse_value(a);
 
                 let instruction = Instruction::NonBasic(operation, value_a);
                 MetaInstruction::Instruction(instruction)
             }
+            Rule::halt_instruction => {
+                let value_a = match record.into_inner().next() {
+                    Some(a) => {
+                        label_locations.record_references(&a);
+                        parse_value(a)
+                    }
+                    None => Value::Static(InstructionArgument::Literal(0)),
+                };
+
+                let instruction = Instruction::NonBasic(NonBasicOperationName::HLT, value_a);
+                MetaInstruction::Instruction(instruction)
+            }
+            Rule::return_instruction => MetaInstruction::Instruction(Instruction::Basic(
+                BasicOperationName::SET,
+                InstructionArgument::SpecialRegister(SpecialRegister::ProgramCounter),
+                Value::Static(InstructionArgument::StackOperation(StackOperation::Pop)),
+            )),
+            Rule::push_instruction => {
+                let value = record.into_inner().next().unwrap();
+                label_locations.record_references(&value);
+
+                let instruction = Instruction::Basic(
+                    BasicOperationName::SET,
+                    InstructionArgument::StackOperation(StackOperation::Push),
+                    parse_value(value),
+                );
+                MetaInstruction::Instruction(instruction)
+            }
+            Rule::pop_instruction => {
+                let target = record.into_inner().next().unwrap();
+
+                let instruction = Instruction::Basic(
+                    BasicOperationName::SET,
+                    parse_instruction_argument(target),
+                    Value::Static(InstructionArgument::StackOperation(StackOperation::Pop)),
+                );
+                MetaInstruction::Instruction(instruction)
+            }
+            Rule::jump_instruction => {
+                let target = record.into_inner().next().unwrap();
+                label_locations.record_references(&target);
+
+                let instruction = Instruction::Jump {
+                    target: parse_value(target),
+                    relative: true,
+                };
+                MetaInstruction::Instruction(instruction)
+            }
+            Rule::data => {
+                let mut words = Vec::new();
+                for value in record.into_inner() {
+                    label_locations.record_references(&value);
+                    match parse_data_value(value) {
+                        Ok(values) => words.extend(values),
+                        Err(error) => errors.push(error),
+                    }
+                }
+                MetaInstruction::Data(words)
+            }
+            Rule::constant => {
+                let mut constant = record.into_inner();
+                let name = constant.next().unwrap();
+                let value = constant.next().unwrap();
+
+                let name = label_locations.define(&name);
+                label_locations.record_references(&value);
+
+                let location = value
+                    .clone()
+                    .into_inner()
+                    .flatten()
+                    .find(|inner| inner.as_rule() == Rule::location_counter);
+                if let Some(location) = location {
+                    let (line, column) = location.as_span().start_pos().line_col();
+                    errors.push(AssembleError::LocationInConstant { line, column });
+                    continue;
+                }
+
+                MetaInstruction::Constant(name, parse_expression(value))
+            }
+            Rule::reserve => {
+                let literal = record.into_inner().next().unwrap();
+                MetaInstruction::Reserve(parse_literal_raw(literal.into_inner().next().unwrap()))
+            }
+            Rule::align => {
+                let literal = record.into_inner().next().unwrap();
+                MetaInstruction::Align(parse_literal_raw(literal.into_inner().next().unwrap()))
+            }
+            Rule::origin => {
+                let literal = record.into_inner().next().unwrap();
+                let (line, column) = literal.as_span().start_pos().line_col();
+                let address = parse_literal_raw(literal.into_inner().next().unwrap());
+                MetaInstruction::Origin {
+                    address,
+                    line,
+                    column,
+                }
+            }
+            Rule::include => {
+                let name = record.into_inner().next().unwrap();
+                let (line, column) = name.as_span().start_pos().line_col();
+                let name = String::from(name.into_inner().next().unwrap().as_str());
+
+                if includes.contains(&name) {
+                    errors.push(AssembleError::IncludeCycle { name, line, column });
+                    continue;
+                }
+
+                let included = match resolver(&name) {
+                    Some(included) => included,
+                    None => {
+                        errors.push(AssembleError::UnresolvedInclude { name, line, column });
+                        continue;
+                    }
+                };
+
+                includes.push(name);
+                if let Err(error) = get_meta_instructions(
+                    included,
+                    resolver,
+                    includes,
+                    meta_instructions,
+                    label_locations,
+                ) {
+                    errors.extend(error.into_errors());
+                }
+                includes.pop();
+                continue;
+            }
             Rule::EOI => {
                 break;
             }
@@ -219,9 +1065,47 @@ where
             }
         };
 
-        meta_instructions.push(token);
+        meta_instructions.push(Token {
+            meta_instruction: token.qualify_local_labels(&label_locations.scope),
+            line,
+            column,
+        });
+    }
+
+    AssembleError::combine(errors)
+}
+
+/// Replaces the content of the specified one-based line with whitespace, keeping the
+/// positions of all other lines intact.
+///
+/// Returns `false` if the line is already blank, i.e. nothing could be skipped.
+fn blank_line(source: &mut String, line: usize) -> bool {
+    let start: usize = source
+        .split('\n')
+        .take(line - 1)
+        .map(|line| line.len() + 1)
+        .sum();
+    if start >= source.len() {
+        return false;
+    }
+
+    let end = source[start..]
+        .find(['\r', '\n'])
+        .map_or(source.len(), |end| start + end);
+    if source[start..end].trim().is_empty() {
+        return false;
     }
-    meta_instructions
+
+    let blank = " ".repeat(source[start..end].chars().count());
+    source.replace_range(start..end, &blank);
+    true
+}
+
+/// A [`MetaInstruction`] and the position of its record in the source code.
+struct Token {
+    meta_instruction: MetaInstruction,
+    line: usize,
+    column: usize,
 }
 
 /// A [`MetaInstruction`] captures the both instruction and
@@ -232,6 +1116,50 @@ enum MetaInstruction {
     Instruction(Instruction),
     /// A label.
     Label(String),
+    /// Raw data words.
+    Data(Vec<Expression>),
+    /// A named constant.
+    Constant(String, Expression),
+    /// The specified number of zero words, e.g. for buffers.
+    Reserve(Word),
+    /// Zero words up to the next multiple of the specified alignment.
+    Align(Word),
+    /// Places the following instructions at the specified address.
+    Origin {
+        address: Word,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl MetaInstruction {
+    /// Qualifies the local label references with the name of the enclosing global label.
+    fn qualify_local_labels(self, scope: &str) -> Self {
+        match self {
+            Self::Instruction(Instruction::Basic(operation, a, b)) => Self::Instruction(
+                Instruction::Basic(operation, a, b.qualify_local_labels(scope)),
+            ),
+            Self::Instruction(Instruction::NonBasic(operation, a)) => Self::Instruction(
+                Instruction::NonBasic(operation, a.qualify_local_labels(scope)),
+            ),
+            Self::Instruction(Instruction::Jump { target, relative }) => {
+                Self::Instruction(Instruction::Jump {
+                    target: target.qualify_local_labels(scope),
+                    relative,
+                })
+            }
+            Self::Data(words) => Self::Data(
+                words
+                    .into_iter()
+                    .map(|word| word.qualify_local_labels(scope))
+                    .collect(),
+            ),
+            Self::Constant(name, expression) => {
+                Self::Constant(name, expression.qualify_local_labels(scope))
+            }
+            _ => self,
+        }
+    }
 }
 
 /// An actual instruction with both its operands.
@@ -241,19 +1169,155 @@ enum Instruction {
     Basic(BasicOperationName, InstructionArgument, Value),
     /// A non-basic (one-operand) instruction.
     NonBasic(NonBasicOperationName, Value),
+    /// The `JMP` pseudo-instruction, assembled as `SET PC, target`, or as
+    /// `ADD PC, distance` or `SUB PC, distance` if `relative` and the distance
+    /// fits into an inline literal.
+    Jump { target: Value, relative: bool },
 }
 
 /// A [`Value`] may either refer to an actual argument or
-/// a reference to a label.
+/// an expression that may refer to labels.
 #[derive(Debug, Clone)]
 enum Value {
     /// A value.
     Static(InstructionArgument),
+    /// An expression that is evaluated once the labels are known.
+    Expression(Expression),
+}
+
+/// A constant expression over literals and label references,
+/// along with its location in the source code.
+#[derive(Debug, Clone)]
+struct Expression {
+    node: ExpressionNode,
+    line: usize,
+    column: usize,
+}
+
+/// A node of an [`Expression`] tree.
+#[derive(Debug, Clone)]
+enum ExpressionNode {
+    /// A literal value.
+    Literal(Word),
     /// A reference to a label.
     LabelReference(String),
+    /// A binary operation.
+    Binary(Box<ExpressionNode>, Operator, Box<ExpressionNode>),
+    /// A unary operation.
+    Unary(UnaryOperator, Box<ExpressionNode>),
+}
+
+/// A binary operator of an expression.
+#[derive(Debug, Copy, Clone)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// A unary operator of an expression.
+#[derive(Debug, Copy, Clone)]
+enum UnaryOperator {
+    /// The two's complement.
+    Negate,
+    /// The bitwise complement.
+    Not,
+}
+
+/// The reason why an expression could not be evaluated.
+enum EvaluationError {
+    DivisionByZero,
+    Overflow,
+}
+
+impl Value {
+    /// Qualifies the local label references with the name of the enclosing global label.
+    fn qualify_local_labels(self, scope: &str) -> Self {
+        match self {
+            Self::Static(_) => self,
+            Self::Expression(expression) => {
+                Self::Expression(expression.qualify_local_labels(scope))
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// Qualifies the local label references with the name of the enclosing global label.
+    fn qualify_local_labels(self, scope: &str) -> Self {
+        Self {
+            node: self.node.qualify_local_labels(scope),
+            ..self
+        }
+    }
+
+    /// Evaluates the expression given the map of jump labels to program addresses.
+    fn evaluate(&self, label_map: &HashMap<String, Word>) -> Result<Word, AssembleError> {
+        let (line, column) = (self.line, self.column);
+        self.node.evaluate(label_map).map_err(|error| match error {
+            EvaluationError::DivisionByZero => AssembleError::DivisionByZero { line, column },
+            EvaluationError::Overflow => AssembleError::Overflow { line, column },
+        })
+    }
+
+    /// Evaluates the expression given the current best guess for the label addresses.
+    ///
+    /// Since labels may still move, errors are ignored here and
+    /// reported by [`Expression::evaluate`] once the labels are final.
+    fn estimate(&self, label_map: &HashMap<String, Word>) -> Word {
+        self.node.evaluate(label_map).unwrap_or(0)
+    }
+}
+
+impl ExpressionNode {
+    /// Qualifies the local label references with the name of the enclosing global label.
+    fn qualify_local_labels(self, scope: &str) -> Self {
+        match self {
+            Self::Literal(_) => self,
+            Self::LabelReference(label) => Self::LabelReference(qualify_label(&label, scope)),
+            Self::Binary(lhs, operator, rhs) => Self::Binary(
+                Box::new(lhs.qualify_local_labels(scope)),
+                operator,
+                Box::new(rhs.qualify_local_labels(scope)),
+            ),
+            Self::Unary(operator, operand) => {
+                Self::Unary(operator, Box::new(operand.qualify_local_labels(scope)))
+            }
+        }
+    }
+
+    /// Evaluates the expression, requiring the result of every binary operation to fit into
+    /// a word. Unary operations are evaluated in two's complement.
+    fn evaluate(&self, label_map: &HashMap<String, Word>) -> Result<Word, EvaluationError> {
+        match self {
+            Self::Literal(value) => Ok(*value),
+            Self::LabelReference(label) => Ok(label_map[label]),
+            Self::Binary(lhs, operator, rhs) => {
+                let lhs = lhs.evaluate(label_map)?;
+                let rhs = rhs.evaluate(label_map)?;
+                let result = match operator {
+                    Operator::Add => lhs.checked_add(rhs),
+                    Operator::Subtract => lhs.checked_sub(rhs),
+                    Operator::Multiply => lhs.checked_mul(rhs),
+                    Operator::Divide if rhs == 0 => return Err(EvaluationError::DivisionByZero),
+                    Operator::Divide => lhs.checked_div(rhs),
+                };
+                result.ok_or(EvaluationError::Overflow)
+            }
+            Self::Unary(operator, operand) => {
+                let operand = operand.evaluate(label_map)?;
+                Ok(match operator {
+                    UnaryOperator::Negate => operand.wrapping_neg(),
+                    UnaryOperator::Not => !operand,
+                })
+            }
+        }
+    }
 }
 
 /// A basic operation with two arguments.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 enum BasicOperationName {
     SET,
@@ -271,11 +1335,29 @@ enum BasicOperationName {
     IFN,
     IFG,
     IFB,
+    MLI,
+    DVI,
+    STI,
+    STD,
+    IFA,
+    IFL,
+    IFU,
 }
 
+/// A non-basic operation with one argument.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 enum NonBasicOperationName {
     JSR,
+    INT,
+    IAG,
+    IAS,
+    RFI,
+    IAQ,
+    HWN,
+    HWQ,
+    HWI,
+    HLT,
 }
 
 impl BasicOperationName {
@@ -301,6 +1383,14 @@ impl BasicOperationName {
             Self::IFN => 0xD,
             Self::IFG => 0xE,
             Self::IFB => 0xF,
+            // Extended instructions, see `prefix()`.
+            Self::MLI => 0x4,
+            Self::DVI => 0x5,
+            Self::STI => 0x1,
+            Self::STD => 0x2,
+            Self::IFA => 0xC,
+            Self::IFL => 0xD,
+            Self::IFU => 0xE,
         };
 
         let a_baked = a.bake_argument();
@@ -309,12 +1399,24 @@ impl BasicOperationName {
         let instruction = ((opcode & 0b1111)
             | ((a_baked.inline as u32 & 0b111_111) << 4)
             | ((b_baked.inline as u32 & 0b111_111) << 10)) as Word;
+
+        // The next word of a precedes the one of b; if a has none, b's directly follows.
         if a_baked.literal.is_some() {
             (instruction, a_baked.literal, b_baked.literal)
         } else {
             (instruction, b_baked.literal, None)
         }
     }
+
+    /// Gets the prefix word of the instruction, if it is an extended instruction.
+    fn prefix(&self) -> Option<Word> {
+        match self {
+            Self::MLI | Self::DVI | Self::STI | Self::STD | Self::IFA | Self::IFL | Self::IFU => {
+                Some(EXTENDED_PREFIX)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl NonBasicOperationName {
@@ -322,6 +1424,15 @@ impl NonBasicOperationName {
     fn bake(&self, a: InstructionArgument) -> (Word, Option<Word>) {
         let opcode = match self {
             Self::JSR => 0x1,
+            Self::INT => 0x8,
+            Self::IAG => 0x9,
+            Self::IAS => 0xa,
+            Self::RFI => 0xb,
+            Self::IAQ => 0xc,
+            Self::HWN => 0x10,
+            Self::HWQ => 0x11,
+            Self::HWI => 0x12,
+            Self::HLT => 0x3d,
         };
 
         let a_baked = a.bake_argument();
@@ -346,7 +1457,7 @@ impl InstructionArgument {
     fn bake_argument(&self) -> MaterializedValue {
         match self {
             Self::Register(register) => MaterializedValue {
-                inline: (*register as usize + 0x00) as u8,
+                inline: *register as u8,
                 literal: None,
             },
             Self::AddressFromRegister(register) => MaterializedValue {
@@ -418,6 +1529,7 @@ enum MaterializedInstruction {
     /// An instruction that is fixed in size.
     Static {
         instruction: Instruction,
+        prefix: Option<Word>,
         instruction_word: Word,
         arg1: Option<Word>,
         arg2: Option<Word>,
@@ -427,10 +1539,25 @@ enum MaterializedInstruction {
     /// an inline literal.
     Flexible {
         instruction: Instruction,
+        prefix: Option<Word>,
         instruction_word: Word,
         arg1: Option<Word>,
         arg2: Option<Word>,
     },
+    /// Raw data that is written verbatim into the bytestream.
+    Data { words: Vec<Expression> },
+    /// The specified number of zero words.
+    Reserve { count: Word },
+    /// The position of a label.
+    Label { label: String },
+    /// Zero words up to the next multiple of the alignment.
+    Align { alignment: Word, padding: Word },
+    /// Zero-fills the bytestream up to the specified address.
+    Origin {
+        address: Word,
+        line: usize,
+        column: usize,
+    },
 }
 
 impl MaterializedInstruction {
@@ -441,11 +1568,15 @@ impl MaterializedInstruction {
         match self {
             Self::Static {
                 instruction: _,
+                prefix,
                 instruction_word: _,
                 arg1,
                 arg2,
             } => {
                 let mut size = 1;
+                if prefix.is_some() {
+                    size += 1;
+                }
                 if arg1.is_some() {
                     size += 1;
                 }
@@ -456,11 +1587,15 @@ impl MaterializedInstruction {
             }
             Self::Flexible {
                 instruction: _,
+                prefix,
                 instruction_word: _,
                 arg1,
                 arg2,
             } => {
                 let mut size = 1;
+                if prefix.is_some() {
+                    size += 1;
+                }
                 if arg1.is_some() {
                     size += 1;
                 }
@@ -469,34 +1604,76 @@ impl MaterializedInstruction {
                 }
                 size
             }
+            Self::Data { words } => words.len(),
+            Self::Reserve { count } => *count as usize,
+            Self::Label { .. } => 0,
+            Self::Align { padding, .. } => *padding as usize,
+            // The gap is determined by the position of the origin.
+            Self::Origin { .. } => 0,
         }
     }
 }
 
 impl Instruction {
-    /// Materializes an instruction given the map of jump labels to program addresses.
-    fn materialize(&self, label_map: &HashMap<String, Word>) -> MaterializedInstruction {
+    /// Materializes an instruction placed at the specified position, given the map
+    /// of jump labels to program addresses.
+    fn materialize(
+        &self,
+        label_map: &HashMap<String, Word>,
+        position: Word,
+    ) -> MaterializedInstruction {
         match self {
+            Instruction::Jump { target, relative } => {
+                let pc = InstructionArgument::SpecialRegister(SpecialRegister::ProgramCounter);
+                match target {
+                    Value::Static(arg) => {
+                        let (opcode, arg1, arg2) = BasicOperationName::SET.bake(pc, *arg);
+                        MaterializedInstruction::Static {
+                            instruction: self.clone(),
+                            prefix: None,
+                            instruction_word: opcode,
+                            arg1,
+                            arg2,
+                        }
+                    }
+                    Value::Expression(expression) => {
+                        let target = expression.estimate(label_map);
+                        let (operation, operand) = jump_operation(target, position, *relative);
+                        let arg = InstructionArgument::Literal(operand);
+                        let (opcode, arg1, arg2) = operation.bake(pc, arg);
+
+                        MaterializedInstruction::Flexible {
+                            instruction: self.clone(),
+                            prefix: None,
+                            instruction_word: opcode,
+                            arg1,
+                            arg2,
+                        }
+                    }
+                }
+            }
             Instruction::NonBasic(nbi, a) => {
                 match a {
                     Value::Static(arg) => {
                         let (opcode, arg1) = nbi.bake(*arg);
                         MaterializedInstruction::Static {
                             instruction: self.clone(),
+                            prefix: None,
                             instruction_word: opcode,
                             arg1,
                             arg2: None,
                         }
                     }
-                    Value::LabelReference(reference) => {
+                    Value::Expression(expression) => {
                         // We now optimistically generate the instruction based on the current
                         // best guess for the label address in the label map.
-                        let address = label_map[reference];
+                        let address = expression.estimate(label_map);
                         let arg = InstructionArgument::Literal(address);
                         let (opcode, arg1) = nbi.bake(arg);
 
                         MaterializedInstruction::Flexible {
                             instruction: self.clone(),
+                            prefix: None,
                             instruction_word: opcode,
                             arg1,
                             arg2: None,
@@ -511,20 +1688,22 @@ impl Instruction {
                         let (opcode, arg1, arg2) = bi.bake(*a, *arg2);
                         MaterializedInstruction::Static {
                             instruction: self.clone(),
+                            prefix: bi.prefix(),
                             instruction_word: opcode,
                             arg1,
                             arg2,
                         }
                     }
-                    Value::LabelReference(reference) => {
+                    Value::Expression(expression) => {
                         // We now optimistically generate the instruction based on the current
                         // best guess for the label address in the label map.
-                        let address = label_map[reference];
+                        let address = expression.estimate(label_map);
                         let arg2 = InstructionArgument::Literal(address);
                         let (opcode, arg1, arg2) = bi.bake(*a, arg2);
 
                         MaterializedInstruction::Flexible {
                             instruction: self.clone(),
+                            prefix: bi.prefix(),
                             instruction_word: opcode,
                             arg1,
                             arg2,
@@ -534,10 +1713,64 @@ impl Instruction {
             }
         }
     }
+
+    /// Evaluates the expressions of the instruction placed at the specified position,
+    /// given the final label addresses, in order to report any errors.
+    ///
+    /// Returns the value of the literal operand, if the instruction has an expression.
+    fn evaluate_expressions(
+        &self,
+        label_map: &HashMap<String, Word>,
+        position: Word,
+    ) -> Result<Option<Word>, AssembleError> {
+        match self {
+            Instruction::NonBasic(_, Value::Expression(expression))
+            | Instruction::Basic(_, _, Value::Expression(expression)) => {
+                Ok(Some(expression.evaluate(label_map)?))
+            }
+            Instruction::Jump {
+                target: Value::Expression(expression),
+                relative,
+            } => {
+                let target = expression.evaluate(label_map)?;
+                Ok(Some(jump_operation(target, position, *relative).1))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Gets the instruction with jumps forced into their absolute form.
+    fn expanded(&self) -> Self {
+        match self {
+            Instruction::Jump { target, .. } => Instruction::Jump {
+                target: target.clone(),
+                relative: false,
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Selects the operation and literal operand of a jump placed at the specified position.
+///
+/// Relative jumps are only used if they save a word, i.e. if the target does not fit into
+/// an inline literal but the distance does. Otherwise, `JMP` matches `SET PC, target`.
+fn jump_operation(target: Word, position: Word, relative: bool) -> (BasicOperationName, Word) {
+    if relative && target > 0x1f {
+        // The program counter already points past the single word of a relative jump.
+        let next = position.wrapping_add(1);
+        if let Some(distance) = target.checked_sub(next).filter(|&d| d <= 0x1f) {
+            return (BasicOperationName::ADD, distance);
+        }
+        if let Some(distance) = next.checked_sub(target).filter(|&d| d <= 0x1f) {
+            return (BasicOperationName::SUB, distance);
+        }
+    }
+    (BasicOperationName::SET, target)
 }
 
 fn parse_basic_operation(pair: Pair<Rule>) -> BasicOperationName {
-    match pair.as_str() {
+    match pair.as_str().to_ascii_uppercase().as_str() {
         "SET" => BasicOperationName::SET,
         "ADD" => BasicOperationName::ADD,
         "SUB" => BasicOperationName::SUB,
@@ -553,13 +1786,28 @@ fn parse_basic_operation(pair: Pair<Rule>) -> BasicOperationName {
         "IFN" => BasicOperationName::IFN,
         "IFG" => BasicOperationName::IFG,
         "IFB" => BasicOperationName::IFB,
+        "MLI" => BasicOperationName::MLI,
+        "DVI" => BasicOperationName::DVI,
+        "STI" => BasicOperationName::STI,
+        "STD" => BasicOperationName::STD,
+        "IFA" => BasicOperationName::IFA,
+        "IFL" => BasicOperationName::IFL,
+        "IFU" => BasicOperationName::IFU,
         _ => unimplemented!(),
     }
 }
 
 fn parse_nonbasic_operation(pair: Pair<Rule>) -> NonBasicOperationName {
-    match pair.as_str() {
+    match pair.as_str().to_ascii_uppercase().as_str() {
         "JSR" => NonBasicOperationName::JSR,
+        "INT" => NonBasicOperationName::INT,
+        "IAG" => NonBasicOperationName::IAG,
+        "IAS" => NonBasicOperationName::IAS,
+        "RFI" => NonBasicOperationName::RFI,
+        "IAQ" => NonBasicOperationName::IAQ,
+        "HWN" => NonBasicOperationName::HWN,
+        "HWQ" => NonBasicOperationName::HWQ,
+        "HWI" => NonBasicOperationName::HWI,
         _ => unimplemented!(),
     }
 }
@@ -579,7 +1827,7 @@ fn parse_value(pair: Pair<Rule>) -> Value {
         Rule::address_with_offset => parse_address_with_offset(pair),
         Rule::special_register => parse_special_register(pair),
         Rule::stack_op => parse_stack_op(pair),
-        Rule::label_ref => parse_label_ref(pair),
+        Rule::expression => parse_expression_value(pair),
         _ => {
             println!("{:?}", pair);
             unreachable!()
@@ -592,7 +1840,7 @@ fn parse_register(pair: Pair<Rule>) -> Value {
 }
 
 fn parse_register_raw(pair: Pair<Rule>) -> Register {
-    match pair.as_str() {
+    match pair.as_str().to_ascii_uppercase().as_str() {
         "A" => Register::A,
         "B" => Register::B,
         "C" => Register::C,
@@ -614,14 +1862,108 @@ fn parse_literal(pair: Pair<Rule>) -> Value {
 fn parse_literal_raw(pair: Pair<Rule>) -> Word {
     match pair.as_rule() {
         Rule::value_dec => {
-            u16::from_str_radix(pair.as_str(), 10).expect("invalid format for decimal literal")
+            parse_decimal(pair.as_str()).expect("decimal literals are checked while parsing")
+        }
+        Rule::value_hex => {
+            let digits = pair.as_str()[2..].replace('_', "");
+            u16::from_str_radix(&digits, 16).expect("invalid format for hex literal")
+        }
+        Rule::value_char => {
+            let content = pair.into_inner().next().unwrap();
+            unescape(content.as_str())[0]
         }
-        Rule::value_hex => u16::from_str_radix(pair.as_str().trim_start_matches("0x"), 16)
-            .expect("invalid format for hex literal"),
         _ => unreachable!(),
     }
 }
 
+/// Parses a decimal literal, interpreting negative values as two's complement.
+fn parse_decimal(literal: &str) -> Option<Word> {
+    match literal.replace('_', "").parse::<i32>().ok()? {
+        value @ -0x8000..=-1 => Some(value as i16 as Word),
+        value @ 0..=0xFFFF => Some(value as Word),
+        _ => None,
+    }
+}
+
+/// Ensures that all decimal literals of a record fit into a word.
+fn check_literals(record: &Pair<Rule>) -> Result<(), AssembleError> {
+    for inner in record.clone().into_inner().flatten() {
+        if inner.as_rule() == Rule::value_dec && parse_decimal(inner.as_str()).is_none() {
+            let (line, column) = inner.as_span().start_pos().line_col();
+            return Err(AssembleError::LiteralOutOfRange {
+                literal: String::from(inner.as_str()),
+                line,
+                column,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Converts the content of a character or string literal into one word per character,
+/// resolving escape sequences.
+fn unescape(content: &str) -> Vec<Word> {
+    let mut words = Vec::with_capacity(content.len());
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(escaped) => escaped,
+                None => unreachable!(),
+            },
+            c => c,
+        };
+        words.push(c as Word);
+    }
+    words
+}
+
+/// Parses a data value, expanding a `DUP` modifier into `count` copies of the value.
+fn parse_data_value(pair: Pair<Rule>) -> Result<Vec<Expression>, AssembleError> {
+    let mut data_value = pair.into_inner();
+
+    let first = data_value.next().unwrap();
+    if first.as_rule() == Rule::string_literal {
+        let (line, column) = first.as_span().start_pos().line_col();
+        let content = first.into_inner().next().unwrap();
+        return Ok(unescape(content.as_str())
+            .into_iter()
+            .map(|word| Expression {
+                node: ExpressionNode::Literal(word),
+                line,
+                column,
+            })
+            .collect());
+    }
+
+    let value = parse_expression(first);
+
+    let count = match data_value.next() {
+        Some(count) => {
+            let literal = count.into_inner().next().unwrap();
+            let text = literal.as_str();
+            let (line, column) = literal.as_span().start_pos().line_col();
+            let negative = text.starts_with('-');
+            match parse_literal_raw(literal) {
+                count if count > 0 && !negative => count,
+                _ => {
+                    return Err(AssembleError::InvalidDupCount {
+                        count: String::from(text),
+                        line,
+                        column,
+                    })
+                }
+            }
+        }
+        None => 1,
+    };
+
+    Ok(vec![value; count as usize])
+}
+
 fn parse_address(pair: Pair<Rule>) -> Value {
     let mut address = pair.into_inner();
 
@@ -663,10 +2005,10 @@ fn parse_address_with_offset(pair: Pair<Rule>) -> Value {
 }
 
 fn parse_special_register(pair: Pair<Rule>) -> Value {
-    let special = match pair.as_str() {
+    let special = match pair.as_str().to_ascii_uppercase().as_str() {
         "SP" => SpecialRegister::StackPointer,
         "PC" => SpecialRegister::ProgramCounter,
-        "O" => SpecialRegister::Overflow,
+        "O" | "EX" => SpecialRegister::Overflow,
         _ => {
             println!("{:?}", pair);
             unreachable!()
@@ -676,7 +2018,7 @@ fn parse_special_register(pair: Pair<Rule>) -> Value {
 }
 
 fn parse_stack_op(pair: Pair<Rule>) -> Value {
-    let op = match pair.as_str() {
+    let op = match pair.as_str().to_ascii_uppercase().as_str() {
         "POP" => StackOperation::Pop,
         "PEEK" => StackOperation::Peek,
         "PUSH" => StackOperation::Push,
@@ -688,6 +2030,1061 @@ fn parse_stack_op(pair: Pair<Rule>) -> Value {
     Value::Static(InstructionArgument::StackOperation(op))
 }
 
-fn parse_label_ref(pair: Pair<Rule>) -> Value {
-    Value::LabelReference(String::from(pair.as_str()))
+fn parse_expression_value(pair: Pair<Rule>) -> Value {
+    let expression = parse_expression(pair);
+    match expression.node {
+        // Plain literals can be inlined right away.
+        ExpressionNode::Literal(word) => Value::Static(InstructionArgument::Literal(word)),
+        _ => Value::Expression(expression),
+    }
+}
+
+fn parse_expression(pair: Pair<Rule>) -> Expression {
+    let (line, column) = pair.as_span().start_pos().line_col();
+    Expression {
+        node: parse_expression_node(pair),
+        line,
+        column,
+    }
+}
+
+/// Parses an expression into a tree of left-associative operations.
+fn parse_expression_node(pair: Pair<Rule>) -> ExpressionNode {
+    match pair.as_rule() {
+        Rule::expression | Rule::term => {
+            let mut inner = pair.into_inner();
+            let mut node = parse_expression_node(inner.next().unwrap());
+            while let Some(operator) = inner.next() {
+                let operator = match operator.as_str() {
+                    "+" => Operator::Add,
+                    "-" => Operator::Subtract,
+                    "*" => Operator::Multiply,
+                    "/" => Operator::Divide,
+                    _ => unreachable!(),
+                };
+                let rhs = parse_expression_node(inner.next().unwrap());
+                node = ExpressionNode::Binary(Box::new(node), operator, Box::new(rhs));
+            }
+            node
+        }
+        Rule::literal => {
+            ExpressionNode::Literal(parse_literal_raw(pair.into_inner().next().unwrap()))
+        }
+        Rule::label_ref => ExpressionNode::LabelReference(String::from(pair.as_str())),
+        Rule::location_counter => ExpressionNode::LabelReference(String::from(LOCATION_COUNTER)),
+        Rule::unary => {
+            let mut inner = pair.into_inner();
+            let operator = match inner.next().unwrap().as_str() {
+                "-" => UnaryOperator::Negate,
+                "~" => UnaryOperator::Not,
+                _ => unreachable!(),
+            };
+            let operand = parse_expression_node(inner.next().unwrap());
+            ExpressionNode::Unary(operator, Box::new(operand))
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dat_dup_works() {
+        let (program, symbols) = assemble_with_symbols(
+            r"
+            :data   DAT 0 DUP 4
+            :next   SET PC, next
+            ",
+        )
+        .unwrap();
+        assert_eq!(symbols["data"], 0x0000);
+        assert_eq!(symbols["next"], 0x0004);
+        assert_eq!(program, vec![0x0000, 0x0000, 0x0000, 0x0000, 0x91c1]);
+    }
+
+    #[test]
+    fn dat_without_dup_emits_single_word() {
+        let program = assemble("DAT 0xFFFF").unwrap();
+        assert_eq!(program, vec![0xFFFF]);
+    }
+
+    #[test]
+    fn dat_list_works() {
+        let (program, symbols) = assemble_with_symbols(
+            r"
+                    SET A, table
+            :table  DAT 0x01, 0x02, 3
+            :end    SET PC, end
+            ",
+        )
+        .unwrap();
+        assert_eq!(symbols["table"], 0x0001);
+        assert_eq!(symbols["end"], 0x0004);
+        assert_eq!(program, vec![0x8401, 0x0001, 0x0002, 0x0003, 0x91c1]);
+    }
+
+    #[test]
+    fn dat_list_with_dup_works() {
+        let program = assemble("DAT 0x01 DUP 2, 0xFFFF").unwrap();
+        assert_eq!(program, vec![0x0001, 0x0001, 0xFFFF]);
+    }
+
+    #[test]
+    fn dat_string_works() {
+        let program = assemble(r#"DAT "Hi\n", 0"#).unwrap();
+        assert_eq!(
+            program,
+            vec![b'H' as Word, b'i' as Word, b'\n' as Word, 0x0000]
+        );
+    }
+
+    #[test]
+    fn dat_string_escapes_work() {
+        let program = assemble(r#"DAT "\t\0\\\"", ';'"#).unwrap();
+        assert_eq!(program, vec![0x0009, 0x0000, 0x005C, 0x0022, 0x003B]);
+    }
+
+    #[test]
+    fn char_literal_works() {
+        let program = assemble("SET A, 'A'").unwrap();
+        assert_eq!(program, vec![0x7c01, 0x0041]);
+
+        let program = assemble(r"SET A, '\n'").unwrap();
+        assert_eq!(program, vec![0xa801]);
+    }
+
+    #[test]
+    fn interrupt_instructions_work() {
+        let program = assemble(
+            r"
+            IAS 5
+            INT 0x07
+            IAQ 1
+            IAG A
+            RFI 0
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0x94a0, 0x9c80, 0x84c0, 0x0090, 0x80b0]);
+    }
+
+    #[test]
+    fn hardware_instructions_work() {
+        let program = assemble(
+            r"
+            HWN I
+            HWQ 0
+            HWI 1
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0x1900, 0x8110, 0x8520]);
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let plain = assemble(
+            r"
+            .def size 2
+            :start SET A, size
+            SET [0x1000], 0x20
+            :loop JSR sub
+            HLT
+            :sub DAT 0x10, size
+            RESW 1
+            HLT 1
+            ",
+        )
+        .unwrap();
+
+        // Trailing whitespace is spelled out since editors tend to strip it.
+        let commented = [
+            "",
+            "; A full-line comment before the first label.",
+            "",
+            ".def size 2 ; the size",
+            "",
+            ";:start SET A, 3",
+            ":start SET A, size   ",
+            "SET [0x1000], 0x20;no space",
+            "\t \t",
+            "",
+            ":loop   ; a label on its own line; with ; semicolons\t",
+            "\tJSR sub     ; call",
+            "HLT; halt",
+            ";",
+            ":sub DAT 0x10, size ; data",
+            "RESW 1 ; reserved  \t",
+            "HLT 1 ; halt again",
+            "",
+            "; A comment at the end.",
+        ]
+        .join("\n");
+        assert_eq!(assemble(&commented).unwrap(), plain);
+        assert_eq!(assemble(format!("{}\n\n", commented)).unwrap(), plain);
+        assert_eq!(assemble(commented.replace('\n', "\r\n")).unwrap(), plain);
+    }
+
+    #[test]
+    fn literal_formats_work() {
+        let program = assemble(
+            r"
+            DAT 0XFF, 0xff, 0xFf, 0xFF_FF, 1_000, -1_0
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0xff, 0xff, 0xff, 0xffff, 1000, -10i16 as u16]);
+    }
+
+    #[test]
+    fn stack_operations_work() {
+        let program = assemble(
+            r"
+            SET PUSH, A
+            SET A, POP
+            SET A, PEEK
+            SET PEEK, POP
+            SET PUSH, 0x1000
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![0x01a1, 0x6001, 0x6401, 0x6191, 0x7da1, 0x1000]
+        );
+    }
+
+    #[test]
+    fn operand_words_are_ordered_a_then_b() {
+        let program = assemble(
+            r"
+            SET [0x1000], 0x2000
+            SET [0x1000+I], 0x1F
+            SET A, 0x2000
+            IFE [0x1000], [0x2000+J]
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![0x7de1, 0x1000, 0x2000, 0xfd61, 0x1000, 0x7c01, 0x2000, 0x5dec, 0x1000, 0x2000]
+        );
+    }
+
+    #[test]
+    fn assemble_with_notes_works() {
+        let (program, notes) = assemble_with_notes(
+            r"
+            :start  SET A, 0x30
+                    SET PC, start
+                    SET PC, end
+                    DAT 0x1000 DUP 0x20
+            :end    SET PC, end
+            ",
+        )
+        .unwrap();
+
+        // SET PC, start is a single word; SET PC, end requires the next word.
+        assert_eq!(program[2..5], [0x81c1, 0x7dc1, 0x0025]);
+        assert_eq!(
+            notes,
+            vec![AssembleNote::InlinedLiteral {
+                value: 0x00,
+                address: 0x0002
+            }]
+        );
+        assert_eq!(notes[0].to_string(), "literal 0x00 at word 2 was inlined");
+    }
+
+    #[test]
+    fn relative_jumps_work() {
+        let program = assemble(
+            r"
+                    SET PC, start
+                    .org 0x40
+            :start  JMP skip
+                    SET A, 1
+            :skip   SET B, 2
+                    JMP far
+                    RESW 40
+            :far    SET C, 3
+            :loop   JMP loop
+            ",
+        )
+        .unwrap();
+
+        // ADD PC, 1; SET A, 1; SET B, 2; SET PC, far; ...; SET C, 3; SUB PC, 1
+        assert_eq!(
+            program[0x40..0x45],
+            [0x85c2, 0x8401, 0x8811, 0x7dc1, 0x006d]
+        );
+        assert_eq!(program[0x6d..], [0x8c21, 0x85c3]);
+
+        let mut cpu = crate::DCPU16::new(&program);
+        assert_eq!(
+            cpu.run_until_break(),
+            crate::RunOutcome::CrashLoop { pc: 0x006E }
+        );
+        assert_eq!(cpu.register(Register::A), 0);
+        assert_eq!(cpu.register(Register::B), 2);
+        assert_eq!(cpu.register(Register::C), 3);
+    }
+
+    #[test]
+    fn relative_jumps_are_expanded_when_out_of_range() {
+        let (program, symbols) = assemble_with_symbols(
+            r"
+                    JMP end
+                    SET A, end
+                    RESW 30
+            :end    SET B, 1
+            ",
+        )
+        .unwrap();
+
+        // The jump fits until SET A, end grows; it then needs the absolute form.
+        assert_eq!(symbols["end"], 0x0022);
+        assert_eq!(program[..4], [0x7dc1, 0x0022, 0x7c01, 0x0022]);
+        assert_eq!(program[0x22], 0x8411);
+    }
+
+    #[test]
+    fn extended_instructions_work() {
+        let program = assemble(
+            r"
+            MLI A, B
+            DVI A, 0x02
+            SET PC, end
+            :end SET PC, end
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![0x03e0, 0x0404, 0x03e0, 0x8805, 0x95c1, 0x95c1]
+        );
+    }
+
+    #[test]
+    fn sti_and_std_work() {
+        let program = assemble(
+            r"
+            STI [I], [J]
+            STD A, 0x02
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0x03e0, 0x3ce1, 0x03e0, 0x8802]);
+    }
+
+    #[test]
+    fn signed_tests_work() {
+        let program = assemble(
+            r"
+            IFA A, 1
+            IFL A, 1
+            IFU A, 1
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![0x03e0, 0x840c, 0x03e0, 0x840d, 0x03e0, 0x840e]
+        );
+    }
+
+    #[test]
+    fn memory_overflow_fails() {
+        for (source, line) in [
+            ("DAT 1 DUP 0xFFFF\nDAT 1 DUP 2", 2),
+            ("DAT 1 DUP 0xFFFF, 1 DUP 0xFFFF", 1),
+            ("DAT 1 DUP 0xFFFF\nSET A, 0x1000", 2),
+            ("RESW 0xFFFF\nRESW 2", 2),
+            ("RESW 0xFFFF\nSET A, 0x1000", 2),
+            ("RESW 0xFFF0\n.align 0x20", 2),
+            (".org 0xFFFF\nSET A, 0x100", 2),
+            // The data only outgrows the memory once the instruction has grown.
+            (".org 0xFFFD\nSET A, end\nDAT 0\n:end", 3),
+        ] {
+            let error = assemble(source).unwrap_err();
+            assert!(
+                matches!(error, AssembleError::MemoryOverflow { column: 1, .. }),
+                "{:?}",
+                error
+            );
+            assert_eq!(error.line(), line, "{}", source);
+        }
+    }
+
+    #[test]
+    fn dat_dup_zero_fails() {
+        let result = assemble("DAT 0xFFFF DUP 0");
+        assert!(result.is_err());
+
+        for (source, column) in [("DAT 0xFFFF DUP 0", 16), ("DAT 1, 2 DUP -1", 14)] {
+            let error = assemble(source).unwrap_err();
+            assert!(
+                matches!(error, AssembleError::InvalidDupCount { line: 1, .. }),
+                "{:?}",
+                error
+            );
+            assert_eq!(error.column(), column, "{}", source);
+        }
+    }
+
+    #[test]
+    fn duplicate_label_fails() {
+        let result = assemble(
+            r"
+            :loop   SET PC, loop
+            :loop   SET PC, loop
+            ",
+        );
+        assert!(matches!(
+            result,
+            Err(AssembleError::DuplicateLabel { label, line: 3, column: 14 }) if label == "loop"
+        ));
+    }
+
+    #[test]
+    fn undefined_label_fails() {
+        let result = assemble("SET PC, nowhere");
+        assert!(matches!(
+            result,
+            Err(AssembleError::UndefinedLabel { label, line: 1, column: 9 }) if label == "nowhere"
+        ));
+    }
+
+    #[test]
+    fn invalid_syntax_fails() {
+        let result = assemble("SET A");
+        assert!(matches!(result, Err(AssembleError::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn multiple_errors_are_reported() {
+        let source = "SET A, 0x30\n\
+                      SET B\n\
+                      :loop SUB A, 1\n\
+                      IFN A, 0, 1\n\
+                      SET PC, loop\n";
+        let error = assemble(source).unwrap_err();
+        let lines: Vec<usize> = error.errors().iter().map(|error| error.line()).collect();
+        assert_eq!(lines, [2, 4]);
+        assert!(error
+            .errors()
+            .iter()
+            .all(|error| matches!(error, AssembleError::Parse { .. })));
+        let message = error.to_string();
+        assert!(message.starts_with("line 2, column 1: "));
+        assert!(message.contains("\nline 4, column 9: "));
+
+        // Label errors are collected as well.
+        let error = assemble("SET PC, nowhere\n:a SET A, 1\n:a SET PC, elsewhere").unwrap_err();
+        assert!(matches!(
+            error.errors(),
+            [
+                AssembleError::UndefinedLabel { line: 1, .. },
+                AssembleError::DuplicateLabel { line: 3, .. },
+                AssembleError::UndefinedLabel { line: 3, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn includes_work() {
+        let source = "SET A, 0x30\n\
+                      JSR double\n\
+                      HLT\n\
+                      .include \"math.dasm\"\n";
+        let resolver = |name: &str| match name {
+            "math.dasm" => Some(String::from(":double SHL A, 1\nSET PC, POP")),
+            _ => None,
+        };
+        let program = assemble_with_resolver(source, resolver).unwrap();
+        assert_eq!(program, [0x7c01, 0x0030, 0x9010, 0x83d0, 0x8407, 0x61c1]);
+
+        let mut cpu = crate::DCPU16::new(&program);
+        assert_eq!(cpu.run_until_break(), crate::RunOutcome::Halted);
+        assert_eq!(cpu.register(Register::A), 0x60);
+    }
+
+    #[test]
+    fn include_errors_are_reported() {
+        // Without a resolver, nothing can be included.
+        let error = assemble(".include \"math.dasm\"").unwrap_err();
+        assert!(matches!(
+            error,
+            AssembleError::UnresolvedInclude { name, line: 1, column: 10 } if name == "math.dasm"
+        ));
+
+        // a.dasm includes b.dasm, which includes a.dasm again.
+        let resolver = |name: &str| match name {
+            "a.dasm" => Some(String::from("SET A, 1\n.include \"b.dasm\"")),
+            "b.dasm" => Some(String::from(".include \"a.dasm\"")),
+            _ => None,
+        };
+        let error = assemble_with_resolver(".include \"a.dasm\"", resolver).unwrap_err();
+        assert!(matches!(
+            error,
+            AssembleError::IncludeCycle { name, .. } if name == "a.dasm"
+        ));
+    }
+
+    #[test]
+    fn align_works() {
+        let source = "SET PC, table\n\
+                      .align 16\n\
+                      :table DAT 0x1234\n\
+                      .align 4\n\
+                      :end DAT end";
+        let (program, symbols) = assemble_with_symbols(source).unwrap();
+        assert_eq!(symbols["table"], 0x0010);
+        assert_eq!(symbols["end"], 0x0014);
+        assert_eq!(program[0], 0xc1c1);
+        assert!(program[1..0x10].iter().all(|&word| word == 0x0000));
+        assert_eq!(program[0x10..], [0x1234, 0x0000, 0x0000, 0x0000, 0x0014]);
+    }
+
+    #[test]
+    fn align_is_recomputed_when_instructions_grow() {
+        // The jump grows to two words once the label moves past 0x1f, which removes
+        // the padding in front of the aligned label.
+        let source = "SET PC, target\n\
+                      .align 2\n\
+                      :aligned DAT aligned\n\
+                      RESW 0x1e\n\
+                      :target SET PC, target";
+        let (program, symbols) = assemble_with_symbols(source).unwrap();
+        assert_eq!(symbols["aligned"], 0x0002);
+        assert_eq!(symbols["target"], 0x0021);
+        assert_eq!(program[..3], [0x7dc1, 0x0021, 0x0002]);
+        assert_eq!(program.len(), 0x23);
+    }
+
+    #[test]
+    fn location_counter_works() {
+        let source = "SET A, $\n\
+                      SET PC, table\n\
+                      RESW 0x20\n\
+                      :table DAT ., $ + 1, 0x1234\n\
+                      :here DAT .";
+        let (program, symbols) = assemble_with_symbols(source).unwrap();
+        assert_eq!(symbols["here"], 0x0026);
+        assert!(!symbols.contains_key(LOCATION_COUNTER));
+        assert_eq!(program[..3], [0x8001, 0x7dc1, 0x0023]);
+        assert_eq!(program[0x23..], [0x0023, 0x0025, 0x1234, 0x0026]);
+
+        // Constants have no address.
+        let error = assemble(".def size $ - start").unwrap_err();
+        assert!(matches!(
+            error,
+            AssembleError::LocationInConstant {
+                line: 1,
+                column: 11
+            }
+        ));
+    }
+
+    #[test]
+    fn units_work() {
+        let program = "SET A, 0x30\n\
+                       JSR double\n\
+                       :.end SET PC, .end";
+        let library = ":double SHL A, 1\n\
+                       :.end SET PC, POP";
+        let assembled = assemble_units(&[program, library]).unwrap();
+        assert_eq!(assembled, [0x7c01, 0x0030, 0x9010, 0x8dc1, 0x8407, 0x61c1]);
+
+        let mut cpu = crate::DCPU16::new(&assembled);
+        assert_eq!(
+            cpu.run_until_break(),
+            crate::RunOutcome::CrashLoop { pc: 0x0003 }
+        );
+        assert_eq!(cpu.register(Register::A), 0x60);
+
+        // Labels are shared, hence they must be unique across the units.
+        let error = assemble_units(&[":double SET PC, double", library]).unwrap_err();
+        assert!(matches!(
+            error,
+            AssembleError::DuplicateLabel { label, line: 1, .. } if label == "double"
+        ));
+    }
+
+    #[test]
+    fn undefined_label_reports_line() {
+        let source = "SET A, 0x30\n\
+                      :loop SUB A, 1\n\
+                      IFN A, 0\n    \
+                      SET PC, lopo\n";
+        let error = assemble(source).unwrap_err();
+        assert!(matches!(error, AssembleError::UndefinedLabel { .. }));
+        assert_eq!(error.line(), 4);
+        assert_eq!(error.column(), 13);
+    }
+
+    #[test]
+    fn constant_expression_works() {
+        let program = assemble("SET A, 0x10+0x20").unwrap();
+        assert_eq!(program, vec![0x7c01, 0x0030]);
+    }
+
+    #[test]
+    fn label_expression_works() {
+        let program = assemble(
+            r"
+            :loop SET A, 1
+                  SET PC, loop+1
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0x8401, 0x85c1]);
+    }
+
+    #[test]
+    fn parenthesized_expression_works() {
+        let program = assemble(
+            r"
+            SET A, 2*(3+4)
+            DAT (1024/2 - 2) * 2, 2+3*4, end-1
+            :end
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0xb801, 0x03fc, 0x000e, 0x0003]);
+    }
+
+    #[test]
+    fn division_by_zero_fails() {
+        let error = assemble(
+            "SET A, 1
+SET B, 4/(end-end)
+:end",
+        )
+        .unwrap_err();
+        assert!(matches!(error, AssembleError::DivisionByZero { .. }));
+        assert_eq!(error.line(), 2);
+        assert_eq!(error.column(), 8);
+    }
+
+    #[test]
+    fn expression_overflow_fails() {
+        let error = assemble("DAT 0xFFFF+1").unwrap_err();
+        assert!(matches!(error, AssembleError::Overflow { .. }));
+
+        let error = assemble(":start DAT start-1").unwrap_err();
+        assert!(matches!(error, AssembleError::Overflow { .. }));
+    }
+
+    #[test]
+    fn constants_work() {
+        let program = assemble(
+            r"
+            .def WIDTH 32
+            SET A, WIDTH
+            DAT WIDTH, WIDTH/2
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0x7c01, 0x0020, 0x0020, 0x0010]);
+    }
+
+    #[test]
+    fn constants_can_be_forward_referenced() {
+        let program = assemble(
+            r"
+            SET A, HEIGHT
+            DAT LEN
+            :start DAT 1, 2, 3
+            :end
+            .def HEIGHT 4
+            .def LEN end-start
+            ",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0x9001, 0x0003, 0x0001, 0x0002, 0x0003]);
+    }
+
+    #[test]
+    fn constant_colliding_with_label_fails() {
+        let error = assemble(
+            ".def loop 1
+:loop SET PC, loop",
+        )
+        .unwrap_err();
+        assert!(matches!(error, AssembleError::DuplicateLabel { .. }));
+        assert_eq!(error.line(), 2);
+    }
+
+    #[test]
+    fn lowercase_mnemonics_work() {
+        assert_eq!(
+            assemble("set a, 0x30").unwrap(),
+            assemble("SET A, 0x30").unwrap()
+        );
+
+        let lowercase = assemble(
+            r"
+            :crash set push, [i]
+                   ifn o, 0x10
+                   set pc, crash
+                   dat 1 dup 2
+            ",
+        )
+        .unwrap();
+        let uppercase = assemble(
+            r"
+            :crash SET PUSH, [I]
+                   IFN O, 0x10
+                   SET PC, crash
+                   DAT 1 DUP 2
+            ",
+        )
+        .unwrap();
+        assert_eq!(lowercase, uppercase);
+    }
+
+    #[test]
+    fn jmp_works() {
+        let jmp = assemble(
+            r"
+            :crash  JMP crash
+                    JMP A
+                    JMP 0x1000
+            ",
+        )
+        .unwrap();
+        let set = assemble(
+            r"
+            :crash  SET PC, crash
+                    SET PC, A
+                    SET PC, 0x1000
+            ",
+        )
+        .unwrap();
+        assert_eq!(jmp, set);
+    }
+
+    #[test]
+    fn overflow_register_works() {
+        let program = assemble("SET O, 0x5\nSET A, O\nSET EX, A").unwrap();
+        assert_eq!(program, [0x95d1, 0x7401, 0x01d1]);
+
+        let mut cpu = crate::DCPU16::new(&program);
+        cpu.step_n(2);
+        assert_eq!(cpu.overflow, 0x0005);
+        assert_eq!(cpu.register(Register::A), 0x0005);
+    }
+
+    #[test]
+    fn assembled_size_works() {
+        let source = r"
+                    SET A, data
+                    JSR sub
+                    SET PC, end
+            :sub    SET [0x1000+I], end
+                    SET PC, POP
+            .align 8
+            :data   DAT 0x1, 0x2, 0x3
+            RESW 2
+            :end    SET PC, end
+            ";
+        let size = assembled_size(source).unwrap();
+        assert_eq!(size, assemble(source).unwrap().len());
+        assert_eq!(size, 0x0e);
+
+        assert_eq!(assembled_size("").unwrap(), 0);
+        assert!(matches!(
+            assembled_size("SET PC, end").unwrap_err(),
+            AssembleError::UndefinedLabel { .. }
+        ));
+    }
+
+    #[test]
+    fn assemble_line_works() {
+        assert_eq!(assemble_line("SET A, 0x30").unwrap(), [0x7c01, 0x0030]);
+        assert_eq!(assemble_line("SET PC, POP").unwrap(), [0x61c1]);
+        assert_eq!(
+            assemble_line("  SET [0x1000], 0x20 ; comment").unwrap(),
+            [0x7de1, 0x1000, 0x0020]
+        );
+
+        for (line, column, source) in [
+            (1, 1, ""),
+            (1, 1, "SET A, 1\nSET B, 1"),
+            (1, 1, "DAT 0x10"),
+            (1, 2, ":start SET A, 1"),
+            (1, 6, ".def answer 42"),
+        ] {
+            let error = assemble_line(source).unwrap_err();
+            assert!(
+                matches!(error, AssembleError::NotSingleInstruction { .. }),
+                "{:?}: {:?}",
+                source,
+                error
+            );
+            assert_eq!(
+                (error.line(), error.column()),
+                (line, column),
+                "{:?}",
+                source
+            );
+        }
+
+        assert!(matches!(
+            assemble_line("SET PC, start").unwrap_err(),
+            AssembleError::UndefinedLabel { .. }
+        ));
+    }
+
+    #[test]
+    fn literal_write_policy_works() {
+        // The operands are swapped by mistake.
+        let program = assemble("SET A, 0x3\nSET 0x5, A\nSET B, A").unwrap();
+
+        let mut cpu = crate::DCPU16::new(&program);
+        assert_eq!(cpu.run_to_completion(), crate::RunOutcome::EndOfProgram);
+        assert_eq!(cpu.register(Register::B), 0x0003);
+
+        let mut cpu = crate::DCPU16::new(&program);
+        cpu.set_literal_write_policy(crate::LiteralWritePolicy::Error);
+        assert_eq!(
+            cpu.run_to_completion(),
+            crate::RunOutcome::LiteralWrite { pc: 0x0001 }
+        );
+        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.register(Register::B), 0x0000);
+    }
+
+    #[test]
+    fn stack_shorthands_work() {
+        let short = assemble(
+            r"
+            :sub    PUSH A
+                    PUSH 0x1000
+                    PUSH sub
+                    POP [0x2000+I]
+                    POP B
+                    RET
+            ",
+        )
+        .unwrap();
+        let long = assemble(
+            r"
+            :sub    SET PUSH, A
+                    SET PUSH, 0x1000
+                    SET PUSH, sub
+                    SET [0x2000+I], POP
+                    SET B, POP
+                    SET PC, POP
+            ",
+        )
+        .unwrap();
+        assert_eq!(short, long);
+        assert_eq!(short.last(), Some(&0x61c1));
+    }
+
+    #[test]
+    fn labels_are_case_sensitive() {
+        let error = assemble(":Loop SET PC, loop").unwrap_err();
+        assert!(matches!(error, AssembleError::UndefinedLabel { .. }));
+    }
+
+    #[test]
+    fn negative_literals_work() {
+        assert_eq!(assemble("SET A, -1").unwrap(), vec![0x7c01, 0xffff]);
+        assert_eq!(
+            assemble("DAT -128, -32768, 65535, 5-1").unwrap(),
+            vec![0xff80, 0x8000, 0xffff, 0x0004]
+        );
+    }
+
+    #[test]
+    fn unary_operators_work() {
+        assert_eq!(
+            assemble("DAT ~0, -1, ~0x00FF").unwrap(),
+            vec![0xffff, 0xffff, 0xff00]
+        );
+        assert_eq!(
+            assemble(
+                r"
+                .def WIDTH 32
+                .def count 3
+                DAT ~(WIDTH-1), -count, -(2*3), ~WIDTH+1
+                SET A, -count
+                "
+            )
+            .unwrap(),
+            vec![0xffe0, 0xfffd, 0xfffa, 0xffe0, 0x7c01, 0xfffd]
+        );
+    }
+
+    #[test]
+    fn out_of_range_literal_fails() {
+        let error = assemble(
+            "SET A, 0
+DAT 1, 70000",
+        )
+        .unwrap_err();
+        assert!(matches!(error, AssembleError::LiteralOutOfRange { .. }));
+        assert_eq!(error.line(), 2);
+        assert_eq!(error.column(), 8);
+
+        let error = assemble("SET A, -32769").unwrap_err();
+        assert!(matches!(error, AssembleError::LiteralOutOfRange { .. }));
+    }
+
+    #[test]
+    fn origin_works() {
+        let program = assemble(
+            "SET A, 1
+.org 0x10
+:start SET PC, start",
+        )
+        .unwrap();
+        let mut expected = vec![0x0000; 0x11];
+        expected[0x00] = 0x8401;
+        expected[0x10] = 0xc1c1;
+        assert_eq!(program, expected);
+    }
+
+    #[test]
+    fn origin_after_grown_instruction_works() {
+        let program = assemble(
+            "SET PC, far
+:here SET A, here
+.org 0x40
+:far SET PC, far",
+        )
+        .unwrap();
+        assert_eq!(program.len(), 0x42);
+        assert_eq!(program[..3], [0x7dc1, 0x0040, 0x8801]);
+        assert!(program[3..0x40].iter().all(|&word| word == 0));
+        assert_eq!(program[0x40..], [0x7dc1, 0x0040]);
+    }
+
+    #[test]
+    fn backwards_origin_fails() {
+        let error = assemble(
+            "SET A, 0x30
+.org 0x01",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            AssembleError::OriginOverlap { address: 0x01, .. }
+        ));
+        assert_eq!(error.line(), 2);
+        assert_eq!(error.column(), 6);
+    }
+
+    #[test]
+    fn label_references_in_data_work() {
+        let program = assemble(
+            ":jumptab DAT handler0, 0x1234, handler1, handler2
+            SET PC, handler2
+            :handler0 SET A, 1
+            :handler1 SET A, 2
+            RESW 32
+            :handler2 SET PC, handler2",
+        )
+        .unwrap();
+
+        // The jump to handler2 grows to two words, moving all handlers.
+        assert_eq!(program[..4], [0x0006, 0x1234, 0x0007, 0x0028]);
+        assert_eq!(program[4..8], [0x7dc1, 0x0028, 0x8401, 0x8801]);
+        assert_eq!(program[0x28..], [0x7dc1, 0x0028]);
+    }
+
+    #[test]
+    fn local_labels_work() {
+        let program = assemble(
+            ":first SET A, 2
+            :.loop SUB A, 1
+            IFN A, 0
+            SET PC, .loop
+            :second SET B, 2
+            :.loop SUB B, 1
+            IFN B, 0
+            SET PC, .loop",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![0x8801, 0x8403, 0x800d, 0x85c1, 0x8811, 0x8413, 0x801d, 0x95c1]
+        );
+    }
+
+    #[test]
+    fn undefined_local_label_fails() {
+        let error = assemble(
+            ":first SET PC, .loop
+            :second
+            :.loop SET PC, .loop",
+        )
+        .unwrap_err();
+        assert!(
+            matches!(error, AssembleError::UndefinedLabel { ref label, .. } if label == "first.loop")
+        );
+    }
+
+    #[test]
+    fn assemble_with_symbols_works() {
+        let (program, symbols) = assemble_with_symbols(
+            r"
+                      SET A, 0x30
+                      SET [0x1000], 0x20
+                      SUB A, [0x1000]
+                      IFN A, 0x10
+                         SET PC, crash
+                      SET I, 10
+                      SET A, 0x2000
+            :loop     SET [0x2000+I], [A]
+                      SUB I, 1
+                      IFN I, 0
+                         SET PC, loop
+                      SET X, 0x4
+                      JSR testsub
+                      SET PC, crash
+            :testsub  SHL X, 4
+                      SET PC, POP
+            .def      ANSWER 42
+            :crash    SET PC, crash
+            ",
+        )
+        .unwrap();
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols["loop"], 0x000C);
+        assert_eq!(symbols["testsub"], 0x0014);
+        assert_eq!(symbols["crash"], 0x0016);
+        assert_eq!(program[0x0014], 0x9037);
+    }
+
+    #[test]
+    fn halt_works() {
+        assert_eq!(assemble("HLT").unwrap(), vec![0x83d0]);
+        assert_eq!(assemble("hlt ; stop").unwrap(), vec![0x83d0]);
+        assert_eq!(assemble("HLT 1").unwrap(), vec![0x87d0]);
+    }
+
+    #[test]
+    fn ex_register_works() {
+        assert_eq!(assemble("SET EX, 0x1").unwrap(), vec![0x85d1]);
+        assert_eq!(assemble("SET O, 0x1").unwrap(), vec![0x85d1]);
+    }
+
+    #[test]
+    fn reserve_works() {
+        let program = assemble(
+            "SET A, buf
+:buf RESW 16
+:after SET B, after - buf",
+        )
+        .unwrap();
+        assert_eq!(program[0], 0x8401);
+        assert!(program[1..17].iter().all(|&word| word == 0));
+        assert_eq!(program[17..], [0xc011]);
+    }
 }
@@ -0,0 +1,200 @@
+//! Conversion of DCPU-16 program bytecode from and to binary files.
+
+use crate::memory::Ram;
+use crate::{Word, DCPU16, NUM_RAM_WORDS};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// An error that occurred while loading a program.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The number of bytes is odd, i.e. the last word is incomplete.
+    OddByteCount(usize),
+    /// The program could not be read.
+    Io(std::io::Error),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OddByteCount(count) => write!(
+                f,
+                "Expected an even number of bytes, but got {} bytes",
+                count
+            ),
+            Self::Io(error) => write!(f, "Failed to read program: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Packs pairs of big-endian bytes into words.
+pub fn from_bytes_be(bytes: &[u8]) -> Result<Vec<Word>, LoadError> {
+    from_bytes(bytes, Word::from_be_bytes)
+}
+
+/// Packs pairs of little-endian bytes into words.
+pub fn from_bytes_le(bytes: &[u8]) -> Result<Vec<Word>, LoadError> {
+    from_bytes(bytes, Word::from_le_bytes)
+}
+
+/// Unpacks words into pairs of big-endian bytes.
+pub fn to_bytes_be(words: &[Word]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+/// Unpacks words into pairs of little-endian bytes.
+pub fn to_bytes_le(words: &[Word]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Reads big-endian words until the end of the reader.
+pub fn from_reader_be<R: Read>(mut reader: R) -> Result<Vec<Word>, LoadError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    from_bytes_be(&bytes)
+}
+
+/// Reads little-endian words until the end of the reader.
+pub fn from_reader_le<R: Read>(mut reader: R) -> Result<Vec<Word>, LoadError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    from_bytes_le(&bytes)
+}
+
+/// Loads a program from a big-endian binary file, e.g. a `.bin` file.
+pub fn load_be<P: AsRef<Path>>(path: P) -> Result<Vec<Word>, LoadError> {
+    from_reader_be(File::open(path)?)
+}
+
+/// Loads a program from a little-endian binary file.
+pub fn load_le<P: AsRef<Path>>(path: P) -> Result<Vec<Word>, LoadError> {
+    from_reader_le(File::open(path)?)
+}
+
+impl<'p> TryFrom<&[u8]> for DCPU16<'p> {
+    type Error = LoadError;
+
+    /// Creates a CPU from a program given as big-endian bytes, e.g. the contents of a `.bin` file.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let program = from_bytes_be(bytes)?;
+        Ok(DCPU16::with_program(
+            Cow::Owned(program),
+            Ram::zeroed(NUM_RAM_WORDS),
+        ))
+    }
+}
+
+/// Assembles the source into big-endian bytes, e.g. for writing a `.bin` file.
+#[cfg(feature = "file")]
+pub fn assemble_to_be_bytes<T: AsRef<str>>(source: T) -> Result<Vec<u8>, crate::AssembleError> {
+    Ok(to_bytes_be(&crate::assemble(source)?))
+}
+
+/// Disassembles a program given as big-endian bytes, e.g. read from a `.bin` file, into a listing.
+///
+/// See [`disassemble_listing`](crate::disassembler::disassemble_listing).
+#[cfg(feature = "file")]
+pub fn disassemble_be_bytes(bytes: &[u8]) -> Result<String, LoadError> {
+    Ok(crate::disassembler::disassemble_listing(
+        &from_bytes_be(bytes)?,
+        &crate::disassembler::DisassemblyOptions::default(),
+    ))
+}
+
+/// Packs pairs of bytes into words using the specified conversion.
+fn from_bytes<F>(bytes: &[u8], to_word: F) -> Result<Vec<Word>, LoadError>
+where
+    F: Fn([u8; 2]) -> Word,
+{
+    let pairs = bytes.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(LoadError::OddByteCount(bytes.len()));
+    }
+
+    Ok(pairs.map(|pair| to_word([pair[0], pair[1]])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: [Word; 4] = [0x7c01, 0x0030, 0x7de1, 0x1000];
+
+    #[test]
+    fn big_endian_round_trip_works() {
+        let bytes = to_bytes_be(&PROGRAM);
+        assert_eq!(bytes, vec![0x7c, 0x01, 0x00, 0x30, 0x7d, 0xe1, 0x10, 0x00]);
+        assert_eq!(from_bytes_be(&bytes).unwrap(), PROGRAM);
+        assert_eq!(from_reader_be(bytes.as_slice()).unwrap(), PROGRAM);
+    }
+
+    #[test]
+    fn little_endian_round_trip_works() {
+        let bytes = to_bytes_le(&PROGRAM);
+        assert_eq!(bytes, vec![0x01, 0x7c, 0x30, 0x00, 0xe1, 0x7d, 0x00, 0x10]);
+        assert_eq!(from_bytes_le(&bytes).unwrap(), PROGRAM);
+        assert_eq!(from_reader_le(bytes.as_slice()).unwrap(), PROGRAM);
+    }
+
+    #[test]
+    fn odd_byte_count_fails() {
+        assert!(matches!(
+            from_bytes_be(&[0x7c, 0x01, 0x00]),
+            Err(LoadError::OddByteCount(3))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "file")]
+    fn assemble_and_disassemble_bytes_works() {
+        let bytes = assemble_to_be_bytes(
+            r"
+            SET A, 0x30
+            SET [0x1000], 0x20
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            bytes,
+            to_bytes_be(&[0x7c01, 0x0030, 0x7de1, 0x1000, 0x0020])
+        );
+        assert_eq!(
+            disassemble_be_bytes(&bytes).unwrap(),
+            "0000: 7c01 0030       SET A, 0x30\n0002: 7de1 1000 0020  SET [0x1000], 0x20\n"
+        );
+        assert!(matches!(
+            disassemble_be_bytes(&bytes[1..]),
+            Err(LoadError::OddByteCount(9))
+        ));
+    }
+
+    #[test]
+    fn cpu_from_bytes_works() {
+        let cpu = DCPU16::try_from(&to_bytes_be(&PROGRAM)[..]).unwrap();
+        assert_eq!(cpu.ram()[..PROGRAM.len()], PROGRAM);
+
+        assert!(matches!(
+            DCPU16::try_from(&[0x7c_u8, 0x01, 0x00][..]),
+            Err(LoadError::OddByteCount(3))
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{Register, Word, DCPU16};
+
+/// Configures a [`DCPU16`] before running it.
+#[derive(Debug, Default, Clone)]
+pub struct Dcpu16Builder<'p> {
+    /// The program to load.
+    program: &'p [u16],
+    /// Initial register values.
+    registers: Vec<(Register, Word)>,
+    /// Initial RAM values, applied after the program was loaded.
+    ram: Vec<(Word, Word)>,
+    /// The initial stack pointer.
+    stack_pointer: Option<Word>,
+    /// Addresses at which [`DCPU16::run_until_break`] pauses execution.
+    breakpoints: Vec<Word>,
+}
+
+impl<'p> Dcpu16Builder<'p> {
+    /// Creates a builder for a CPU without a program.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the program to load.
+    pub fn program(mut self, program: &'p [u16]) -> Self {
+        self.program = program;
+        self
+    }
+
+    /// Sets the initial value of a register.
+    pub fn register(mut self, register: Register, value: Word) -> Self {
+        self.registers.push((register, value));
+        self
+    }
+
+    /// Sets the initial value at the specified address in RAM.
+    ///
+    /// This is applied after the program was loaded and hence may patch the program.
+    pub fn ram(mut self, address: Word, value: Word) -> Self {
+        self.ram.push((address, value));
+        self
+    }
+
+    /// Sets the initial stack pointer.
+    pub fn stack_pointer(mut self, stack_pointer: Word) -> Self {
+        self.stack_pointer = Some(stack_pointer);
+        self
+    }
+
+    /// Adds a breakpoint, see [`DCPU16::add_breakpoint`].
+    pub fn breakpoint(mut self, addr: Word) -> Self {
+        self.breakpoints.push(addr);
+        self
+    }
+
+    /// Builds the CPU.
+    pub fn build(self) -> DCPU16<'p> {
+        let mut cpu = DCPU16::new(self.program);
+        for (register, value) in self.registers {
+            cpu.set_register(register, value);
+        }
+        for (address, value) in self.ram {
+            cpu.set_ram(address, value);
+        }
+        if let Some(stack_pointer) = self.stack_pointer {
+            cpu.stack_pointer = stack_pointer;
+        }
+        for addr in self.breakpoints {
+            cpu.add_breakpoint(addr);
+        }
+        cpu
+    }
+}
+
+impl<'p> DCPU16<'p> {
+    /// Creates a [`Dcpu16Builder`] for configuring a CPU.
+    pub fn builder() -> Dcpu16Builder<'p> {
+        Dcpu16Builder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RunOutcome;
+
+    #[test]
+    fn builder_works() {
+        // ADD A, 1
+        // ADD A, 1
+        // :here SET PC, here
+        let program = [0x8402, 0x8402, 0x89c1];
+        let mut cpu = DCPU16::builder()
+            .program(&program)
+            .register(Register::A, 0x0010)
+            .ram(0x1000, 0xbeef)
+            .stack_pointer(0x8000)
+            .breakpoint(0x0001)
+            .build();
+
+        assert_eq!(cpu.register(Register::A), 0x0010);
+        assert_eq!(cpu.ram()[0x1000], 0xbeef);
+        assert_eq!(cpu.ram()[..3], program);
+        assert_eq!(cpu.stack_pointer, 0x8000);
+        assert_eq!(cpu.program_counter, 0x0000);
+
+        assert_eq!(cpu.run_until_break(), RunOutcome::Breakpoint(0x0001));
+        assert_eq!(cpu.register(Register::A), 0x0011);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::log::debug;
+use crate::{Device, Register, Word, DCPU16};
+
+/// The number of device ticks making up one 60th of a second at the nominal
+/// 100 kHz, assuming one cycle per instruction.
+pub const DEFAULT_STEPS_PER_PERIOD: u32 = 100_000 / 60;
+
+/// The generic clock (`0x12d0b402`).
+///
+/// The clock ticks at 60/B Hz as configured through `HWI`:
+///
+/// - `A=0`: sets the tick rate to 60/B Hz; a `B` of zero turns the clock off.
+///   This also resets the elapsed ticks.
+/// - `A=1`: stores the number of ticks since the last rate change in `C`.
+/// - `A=2`: enables interrupts with the message in `B`; a `B` of zero disables them.
+///
+/// Since the emulator has no notion of wall-clock time, time is measured in device ticks,
+/// i.e. executed steps. See [`Clock::with_period`].
+#[derive(Debug, Clone)]
+pub struct Clock {
+    /// The number of device ticks making up one 60th of a second.
+    steps_per_period: u32,
+    /// The divider of the 60 Hz base rate; zero if the clock is turned off.
+    divider: Word,
+    /// The device ticks since the last clock tick.
+    steps: u32,
+    /// The clock ticks since the rate was last set.
+    ticks: Word,
+    /// The interrupt message; zero if interrupts are disabled.
+    message: Word,
+}
+
+impl Clock {
+    /// Creates a clock that is turned off, using [`DEFAULT_STEPS_PER_PERIOD`].
+    pub fn new() -> Self {
+        Self::with_period(DEFAULT_STEPS_PER_PERIOD)
+    }
+
+    /// Creates a clock that is turned off, where one 60th of a second lasts
+    /// the specified number of device ticks.
+    pub fn with_period(steps_per_period: u32) -> Self {
+        Self {
+            steps_per_period: steps_per_period.max(1),
+            divider: 0,
+            steps: 0,
+            ticks: 0,
+            message: 0,
+        }
+    }
+
+    /// Gets the number of clock ticks since the rate was last set.
+    pub fn ticks(&self) -> Word {
+        self.ticks
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Clock {
+    fn id(&self) -> u32 {
+        0x12d0b402
+    }
+
+    fn version(&self) -> u16 {
+        1
+    }
+
+    fn manufacturer(&self) -> u32 {
+        0x00000000
+    }
+
+    fn interrupt(&mut self, cpu: &mut DCPU16) {
+        match cpu.register(Register::A) {
+            0 => {
+                self.divider = cpu.register(Register::B);
+                self.steps = 0;
+                self.ticks = 0;
+                debug!("Clock divider set to {divider}", divider = self.divider);
+            }
+            1 => cpu.set_register(Register::C, self.ticks),
+            2 => self.message = cpu.register(Register::B),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cpu: &mut DCPU16) {
+        if self.divider == 0 {
+            return;
+        }
+
+        self.steps += 1;
+        if self.steps < self.steps_per_period * self.divider as u32 {
+            return;
+        }
+
+        self.steps = 0;
+        self.ticks = self.ticks.wrapping_add(1);
+        if self.message != 0 {
+            cpu.trigger_interrupt(self.message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_works() {
+        // IAQ 0x01
+        // SET A, 0x00
+        // SET B, 0x02
+        // HWI 0x00
+        // SET A, 0x02
+        // SET B, 0x07
+        // HWI 0x00
+        // SET A, 0x01
+        // HWI 0x00
+        let program = [
+            0x84c0, 0x8001, 0x8811, 0x8120, 0x8801, 0x9c11, 0x8120, 0x8401, 0x8120, 0x0000,
+        ];
+        let mut cpu = DCPU16::new(&program);
+        cpu.interrupt_address = 0x1000;
+        cpu.add_device(Box::new(Clock::with_period(1)));
+
+        // The clock ticks every other step once the rate is set, but only
+        // queues interrupts once the message is set.
+        cpu.step_n(7);
+        assert!(cpu.interrupt_queue.is_empty());
+
+        cpu.step();
+        assert_eq!(cpu.interrupt_queue.front(), Some(&0x0007));
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::C), 2);
+        assert_eq!(cpu.interrupt_queue.len(), 1);
+    }
+}
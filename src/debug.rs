@@ -0,0 +1,406 @@
+use crate::instruction_word::{InstructionWord, NonBasicInstruction};
+use crate::log::debug;
+use crate::{Register, RunOutcome, StepResult, Word, DCPU16, STACK_POINTER_INIT};
+
+/// A write to a watched RAM address or register.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum WatchHit {
+    /// A watched RAM address was written.
+    Ram {
+        /// The address that was written.
+        addr: Word,
+        /// The value before the write.
+        old: Word,
+        /// The value after the write.
+        new: Word,
+    },
+    /// A watched register was written.
+    Register {
+        /// The register that was written.
+        register: Register,
+        /// The value before the write.
+        old: Word,
+        /// The value after the write.
+        new: Word,
+    },
+}
+
+impl<'p> DCPU16<'p> {
+    /// Adds a breakpoint at the specified address.
+    pub fn add_breakpoint(&mut self, addr: Word) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Removes the breakpoint at the specified address.
+    pub fn remove_breakpoint(&mut self, addr: Word) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Adds a watchpoint that triggers when the specified RAM address is written.
+    pub fn add_write_watch(&mut self, addr: Word) {
+        self.write_watches.insert(addr);
+    }
+
+    /// Removes the watchpoint of the specified RAM address.
+    pub fn remove_write_watch(&mut self, addr: Word) {
+        self.write_watches.remove(&addr);
+    }
+
+    /// Adds a watchpoint that triggers when an instruction writes the specified register.
+    pub fn add_register_watch(&mut self, register: Register) {
+        self.register_watches.insert(register);
+    }
+
+    /// Removes the watchpoint of the specified register.
+    pub fn remove_register_watch(&mut self, register: Register) {
+        self.register_watches.remove(&register);
+    }
+
+    /// Gets the word `depth` words below the top of the stack, i.e. at `SP + depth`.
+    ///
+    /// The top of the stack has a depth of zero; addresses wrap around.
+    pub fn stack_peek(&self, depth: usize) -> Word {
+        let address = self.stack_pointer.wrapping_add(depth as Word);
+        self.ram[self.ram_index(address)]
+    }
+
+    /// Gets the number of words on the stack, assuming it started at the initial stack pointer.
+    ///
+    /// Returns zero if the stack pointer was moved above its initial value.
+    pub fn stack_depth(&self) -> usize {
+        STACK_POINTER_INIT.saturating_sub(self.stack_pointer as usize)
+    }
+
+    /// Executes the program until the program counter reaches a breakpoint, a watched
+    /// address or register is written, or execution stops for any of the reasons of [`DCPU16::run`].
+    ///
+    /// Breakpoints are checked before an instruction is fetched. The instruction at the
+    /// current program counter is always executed, so that a paused program can be resumed.
+    /// Watchpoints are checked after the instruction that wrote the address.
+    pub fn run_until_break(&mut self) -> RunOutcome {
+        self.run_until(|_| false)
+    }
+
+    /// Executes the current instruction, running called subroutines to completion.
+    ///
+    /// If the current instruction is a `JSR`, execution continues like [`DCPU16::run_until_break`]
+    /// until the subroutine returns, i.e. until the program counter reaches the instruction
+    /// following the call with the stack pointer restored, and returns
+    /// [`RunOutcome::Breakpoint`] with that address. Recursive calls returning to the same
+    /// address are therefore stepped over as well.
+    ///
+    /// Any other instruction is executed like a single [`DCPU16::step`], returning
+    /// [`RunOutcome::StepLimitReached`] unless execution stopped.
+    pub fn step_over(&mut self) -> RunOutcome {
+        let decoded = self.decode_at(self.program_counter);
+        let is_call = matches!(
+            decoded.instruction,
+            InstructionWord::NonBasic(NonBasicInstruction::Jsr { .. })
+        );
+        if !is_call || self.skip_next_intruction {
+            return self
+                .step()
+                .outcome(self.previous_program_counter)
+                .unwrap_or(RunOutcome::StepLimitReached);
+        }
+
+        let return_address = self.program_counter.wrapping_add(decoded.length as Word);
+        let stack_pointer = self.stack_pointer;
+        self.run_until(|cpu| {
+            cpu.program_counter == return_address && cpu.stack_pointer == stack_pointer
+        })
+    }
+
+    /// Executes the program like [`DCPU16::run_until_break`], additionally stopping with
+    /// [`RunOutcome::Breakpoint`] before an instruction for which `stop` returns `true`.
+    fn run_until<F>(&mut self, stop: F) -> RunOutcome
+    where
+        F: Fn(&Self) -> bool,
+    {
+        let mut resuming = true;
+        loop {
+            if !resuming && (stop(self) || self.breakpoints.contains(&self.program_counter)) {
+                debug!(
+                    "Breakpoint reached at PC={pc:04X}",
+                    pc = self.program_counter
+                );
+                return RunOutcome::Breakpoint(self.program_counter);
+            }
+            resuming = false;
+
+            let result = self.step();
+            match self.watch_hit.take() {
+                Some(WatchHit::Ram { addr, old, new }) => {
+                    debug!(
+                        "Watchpoint triggered at {addr:04X}: {old:04X} -> {new:04X}",
+                        addr = addr,
+                        old = old,
+                        new = new
+                    );
+                    return RunOutcome::Watchpoint { addr, old, new };
+                }
+                Some(WatchHit::Register { register, old, new }) => {
+                    debug!(
+                        "Watchpoint triggered at register {register}: {old:04X} -> {new:04X}",
+                        register = register,
+                        old = old,
+                        new = new
+                    );
+                    return RunOutcome::RegisterWrite { register, old, new };
+                }
+                None => {}
+            }
+
+            if let Some(outcome) = result.outcome(self.previous_program_counter) {
+                return outcome;
+            }
+        }
+    }
+
+    /// Executes up to `n` instructions and returns the result of each step.
+    ///
+    /// Stops early if execution stops, e.g. due to a crash loop, or if the program counter
+    /// reaches a breakpoint after the first step.
+    pub fn step_n(&mut self, n: usize) -> Vec<StepResult> {
+        let mut results = Vec::new();
+        while results.len() < n {
+            if !results.is_empty() && self.breakpoints.contains(&self.program_counter) {
+                break;
+            }
+
+            let result = self.step();
+            results.push(result);
+            if result.outcome(self.previous_program_counter).is_some() {
+                break;
+            }
+        }
+        results
+    }
+
+    /// Writes a word to memory and records the write if the address is watched.
+    pub(crate) fn write_ram(&mut self, addr: Word, value: Word) {
+        let old = self.read_memory(addr);
+        self.write_memory(addr, value);
+
+        if self.watch_hit.is_none() && self.write_watches.contains(&addr) {
+            self.watch_hit = Some(WatchHit::Ram {
+                addr,
+                old,
+                new: value,
+            });
+        }
+    }
+
+    /// Writes a register and records the write if the register is watched.
+    pub(crate) fn write_register(&mut self, register: Register, value: Word) {
+        let old = self.registers[register as usize];
+        self.registers[register as usize] = value;
+
+        if self.watch_hit.is_none() && self.register_watches.contains(&register) {
+            self.watch_hit = Some(WatchHit::Register {
+                register,
+                old,
+                new: value,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SAMPLE_PROGRAM;
+    use crate::Register;
+
+    #[test]
+    fn breakpoints_work() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        // The start of the loop.
+        cpu.add_breakpoint(0x000D);
+        assert_eq!(cpu.run_until_break(), RunOutcome::Breakpoint(0x000D));
+        assert_eq!(cpu.program_counter, 0x000D);
+        assert_eq!(cpu.register(Register::I), 10);
+
+        // Execution resumes from the breakpoint.
+        assert_eq!(cpu.run_until_break(), RunOutcome::Breakpoint(0x000D));
+        assert_eq!(cpu.register(Register::I), 9);
+
+        cpu.remove_breakpoint(0x000D);
+        assert_eq!(cpu.run_until_break(), RunOutcome::CrashLoop { pc: 0x001A });
+        assert_eq!(cpu.register(Register::X), 0x40);
+    }
+
+    #[test]
+    fn step_over_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        // JSR testsub
+        cpu.add_breakpoint(0x0014);
+        assert_eq!(cpu.run_until_break(), RunOutcome::Breakpoint(0x0014));
+        assert_eq!(cpu.step_over(), RunOutcome::Breakpoint(0x0016));
+        assert_eq!(cpu.register(Register::X), 0x40);
+        assert_eq!(cpu.stack_depth(), 0);
+
+        // SET PC, crash
+        assert_eq!(cpu.step_over(), RunOutcome::StepLimitReached);
+        assert_eq!(cpu.program_counter, 0x001A);
+        assert_eq!(cpu.step_over(), RunOutcome::CrashLoop { pc: 0x001A });
+    }
+
+    #[test]
+    fn step_over_recursive_call_works() {
+        //       SET A, 3
+        //       JSR rec
+        // :end  SET PC, end
+        // :rec  SUB A, 1
+        //       IFN A, 0
+        //       JSR rec
+        //       SET PC, POP
+        let program = [0x8c01, 0x8c10, 0x89c1, 0x8403, 0x800d, 0x8c10, 0x61c1];
+        let mut cpu = DCPU16::new(&program);
+
+        cpu.add_breakpoint(0x0005);
+        assert_eq!(cpu.run_until_break(), RunOutcome::Breakpoint(0x0005));
+        assert_eq!(cpu.register(Register::A), 2);
+        cpu.remove_breakpoint(0x0005);
+
+        // The inner call returns to the same address, but deeper in the stack.
+        assert_eq!(cpu.step_over(), RunOutcome::Breakpoint(0x0006));
+        assert_eq!(cpu.register(Register::A), 0);
+        assert_eq!(cpu.stack_depth(), 1);
+    }
+
+    #[test]
+    fn stack_inspection_works() {
+        // JSR 0x02
+        // JSR 0x04
+        // JSR 0x06
+        // :crash SET PC, crash
+        let program = [0x8810, 0x0000, 0x9010, 0x0000, 0x9810, 0x0000, 0x99c1];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.stack_depth(), 0);
+
+        assert_eq!(cpu.run_until_break(), RunOutcome::CrashLoop { pc: 0x0006 });
+        assert_eq!(cpu.stack_depth(), 3);
+        assert_eq!(cpu.stack_peek(0), 0x0005);
+        assert_eq!(cpu.stack_peek(1), 0x0003);
+        assert_eq!(cpu.stack_peek(2), 0x0001);
+    }
+
+    #[test]
+    fn step_n_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        // SET A, 0x30; SET [0x1000], 0x20; SUB A, [0x1000]; IFN A, 0x10; SET PC, crash (skipped)
+        let results = cpu.step_n(5);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[4], StepResult::Skipped);
+        assert_eq!(cpu.program_counter, 0x000A);
+
+        // Stops at breakpoints, e.g. the start of the loop.
+        cpu.add_breakpoint(0x000D);
+        assert_eq!(cpu.step_n(50).len(), 2);
+        assert_eq!(cpu.program_counter, 0x000D);
+
+        // Stops when execution stops.
+        cpu.remove_breakpoint(0x000D);
+        let results = cpu.step_n(usize::MAX);
+        assert_eq!(results.last(), Some(&StepResult::CrashLoop));
+    }
+
+    #[test]
+    fn write_watches_work() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        // SET [0x1000], 0x20
+        cpu.add_write_watch(0x1000);
+        assert_eq!(
+            cpu.run_until_break(),
+            RunOutcome::Watchpoint {
+                addr: 0x1000,
+                old: 0x0000,
+                new: 0x0020
+            }
+        );
+        assert_eq!(cpu.program_counter, 0x0005);
+
+        cpu.remove_write_watch(0x1000);
+        assert_eq!(cpu.run_until_break(), RunOutcome::CrashLoop { pc: 0x001A });
+    }
+
+    #[test]
+    fn register_watches_work() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.add_register_watch(Register::X);
+
+        // SET X, 0x4
+        assert_eq!(
+            cpu.run_until_break(),
+            RunOutcome::RegisterWrite {
+                register: Register::X,
+                old: 0x0000,
+                new: 0x0004
+            }
+        );
+
+        // SHL X, 4
+        assert_eq!(
+            cpu.run_until_break(),
+            RunOutcome::RegisterWrite {
+                register: Register::X,
+                old: 0x0004,
+                new: 0x0040
+            }
+        );
+        assert_eq!(cpu.program_counter, 0x0019);
+
+        cpu.remove_register_watch(Register::X);
+        assert_eq!(cpu.run_until_break(), RunOutcome::CrashLoop { pc: 0x001A });
+    }
+
+    #[test]
+    fn register_watches_see_implicit_writes() {
+        // STI A, 0x05
+        // STD A, 0x05
+        let program = [0x03e0, 0x9401, 0x03e0, 0x9402];
+        let mut cpu = DCPU16::new(&program);
+        cpu.add_register_watch(Register::J);
+
+        assert_eq!(
+            cpu.run_until_break(),
+            RunOutcome::RegisterWrite {
+                register: Register::J,
+                old: 0x0000,
+                new: 0x0001
+            }
+        );
+        assert_eq!(
+            cpu.run_until_break(),
+            RunOutcome::RegisterWrite {
+                register: Register::J,
+                old: 0x0001,
+                new: 0x0000
+            }
+        );
+    }
+
+    #[test]
+    fn write_watches_with_wrapped_offset_work() {
+        // SET I, 0x02
+        // SET [0xFFFF+I], 0x1F
+        // SET PC, 0x03
+        let program = [0x8861, 0xfd61, 0xffff, 0x8dc1];
+        let mut cpu = DCPU16::new(&program);
+
+        cpu.add_write_watch(0x0001);
+        assert_eq!(
+            cpu.run_until_break(),
+            RunOutcome::Watchpoint {
+                addr: 0x0001,
+                old: 0xfd61,
+                new: 0x001F
+            }
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use crate::instruction::Instruction;
+use crate::instruction_argument::{InstructionArgument, InstructionArgumentDefinition};
+use crate::instruction_word::{InstructionWord, NonBasicInstruction, EXTENDED_PREFIX};
+use crate::{Decode, Word, DCPU16};
+
+/// The maximum length of an instruction, i.e. an extended prefix, the instruction word
+/// and two operands.
+const MAX_INSTRUCTION_WORDS: usize = 4;
+
+/// An instruction decoded without executing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    /// The decoded instruction word.
+    pub instruction: InstructionWord,
+    /// The number of words of the instruction, including any prefix and operand words.
+    pub length: usize,
+    /// The raw operand words following the instruction word.
+    pub operands: Vec<Word>,
+    /// The addressing mode of the "a" value, including its operand.
+    pub a: InstructionArgument,
+    /// The addressing mode of the "b" value, including its operand, if the instruction has one.
+    pub b: Option<InstructionArgument>,
+}
+
+impl DecodedInstruction {
+    /// Gets the addressing mode of the "a" value, e.g. to find the memory accesses of
+    /// an instruction.
+    pub fn a_mode(&self) -> InstructionArgumentDefinition {
+        self.instruction.unpack().0
+    }
+
+    /// Gets the addressing mode of the "b" value, if the instruction has one.
+    pub fn b_mode(&self) -> Option<InstructionArgumentDefinition> {
+        self.instruction.unpack().1
+    }
+}
+
+/// Decodes the instruction at the beginning of the words, e.g. a program, without a CPU.
+///
+/// Returns `None` if there are no words or the operands of the instruction are missing.
+pub fn decode_instruction(words: &[Word]) -> Option<DecodedInstruction> {
+    let mut raw_instruction = *words.first()?;
+    let mut instruction = InstructionWord::decode(raw_instruction);
+    if raw_instruction == EXTENDED_PREFIX {
+        raw_instruction = *words.get(1)?;
+        instruction = InstructionWord::decode_extended(raw_instruction);
+    }
+
+    let length = instruction.length_in_words();
+    let prefix = instruction.num_prefix_words();
+    let operands = words.get(prefix + 1..length)?.to_vec();
+    let (a, b) = match operands[..] {
+        [] => Instruction::OneWord {
+            instruction,
+            raw_instruction,
+        },
+        [raw_1st] => Instruction::TwoWord {
+            instruction,
+            raw_instruction,
+            raw_1st,
+        },
+        [raw_1st, raw_2nd] => Instruction::ThreeWord {
+            instruction,
+            raw_instruction,
+            raw_1st,
+            raw_2nd,
+        },
+        _ => unreachable!(),
+    }
+    .decode_arguments();
+
+    Some(DecodedInstruction {
+        instruction,
+        length,
+        operands,
+        a,
+        b,
+    })
+}
+
+/// Gets the number of words of the instruction at the specified address of the words,
+/// including any prefix and operand words.
+///
+/// A truncated instruction at the end of the words returns the number of available words,
+/// an address past the end returns zero.
+pub fn instruction_len_at(words: &[Word], addr: Word) -> usize {
+    let words = words.get(addr as usize..).unwrap_or_default();
+    let first = match words.first() {
+        Some(&word) => word,
+        None => return 0,
+    };
+
+    let mut instruction = InstructionWord::decode(first);
+    if first == EXTENDED_PREFIX {
+        if let Some(&word) = words.get(1) {
+            instruction = InstructionWord::decode_extended(word);
+        }
+    }
+    instruction.length_in_words().min(words.len())
+}
+
+/// Iterates the instructions of the words, e.g. a program, without a CPU.
+///
+/// Yields the start address, the decoded instruction word and its operand words.
+/// A trailing instruction whose operands are missing is yielded as raw data, i.e. as a
+/// reserved instruction followed by all remaining words, including the instruction word.
+pub fn instructions(
+    words: &[Word],
+) -> impl Iterator<Item = (Word, InstructionWord, Vec<Word>)> + '_ {
+    let mut address = 0;
+    std::iter::from_fn(move || {
+        let remaining = words
+            .get(address..)
+            .filter(|remaining| !remaining.is_empty())?;
+        let start = address as Word;
+        match decode_instruction(remaining) {
+            Some(decoded) => {
+                address += decoded.length;
+                Some((start, decoded.instruction, decoded.operands))
+            }
+            None => {
+                address = words.len();
+                Some((
+                    start,
+                    InstructionWord::NonBasic(NonBasicInstruction::Reserved),
+                    remaining.to_vec(),
+                ))
+            }
+        }
+    })
+}
+
+impl<'p> DCPU16<'p> {
+    /// Decodes the instruction at the specified address without modifying the CPU.
+    ///
+    /// Addresses past the end of the RAM wrap around to `0x0000`.
+    pub fn decode_at(&self, addr: Word) -> DecodedInstruction {
+        let words: Vec<Word> = (0..MAX_INSTRUCTION_WORDS)
+            .map(|offset| self.ram[self.ram_index(addr.wrapping_add(offset as Word))])
+            .collect();
+        decode_instruction(&words).expect("instructions never exceed the maximum length")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SAMPLE_PROGRAM;
+    use crate::instruction_argument::{SpecialRegister, StackOperation};
+    use crate::Register;
+
+    #[test]
+    fn instruction_len_at_works() {
+        // SET A, 0x30
+        assert_eq!(instruction_len_at(&SAMPLE_PROGRAM, 0x0000), 2);
+        // SET [0x1000], 0x20
+        assert_eq!(instruction_len_at(&SAMPLE_PROGRAM, 0x0002), 3);
+        // IFN A, 0x10
+        assert_eq!(instruction_len_at(&SAMPLE_PROGRAM, 0x0007), 1);
+        // SET PC, crash, truncated
+        assert_eq!(instruction_len_at(&SAMPLE_PROGRAM[..27], 0x001A), 1);
+        assert_eq!(instruction_len_at(&SAMPLE_PROGRAM, 0x001C), 0);
+        // DVI A, 0x30
+        assert_eq!(instruction_len_at(&[0x03e0, 0x7c05, 0x0030], 0x0000), 3);
+    }
+
+    #[test]
+    fn instructions_works() {
+        let instructions: Vec<_> = instructions(&SAMPLE_PROGRAM).collect();
+        assert_eq!(instructions.len(), 17);
+
+        // SET [0x1000], 0x20
+        let (address, instruction, operands) = &instructions[1];
+        assert_eq!(*address, 0x0002);
+        assert!(matches!(instruction, InstructionWord::Set { .. }));
+        assert_eq!(operands, &vec![0x1000, 0x0020]);
+
+        // JSR testsub
+        let (address, instruction, operands) = &instructions[12];
+        assert_eq!(*address, 0x0014);
+        assert!(matches!(
+            instruction,
+            InstructionWord::NonBasic(NonBasicInstruction::Jsr { .. })
+        ));
+        assert_eq!(operands, &vec![0x0018]);
+    }
+
+    #[test]
+    fn instructions_with_truncated_instruction_works() {
+        // SET A, 0x30 with the literal missing.
+        let instructions: Vec<_> = instructions(&[0x8401, 0x7c01]).collect();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(
+            instructions[1],
+            (
+                0x0001,
+                InstructionWord::NonBasic(NonBasicInstruction::Reserved),
+                vec![0x7c01]
+            )
+        );
+    }
+
+    #[test]
+    fn decode_at_works() {
+        let cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        // SET [0x1000], 0x20
+        let decoded = cpu.decode_at(0x0002);
+        assert_eq!(
+            decoded.instruction,
+            InstructionWord::Set {
+                a: InstructionArgumentDefinition::AtAddressFromNextWord,
+                b: InstructionArgumentDefinition::NextWordLiteral,
+            }
+        );
+        assert_eq!(decoded.length, 3);
+        assert_eq!(decoded.operands, vec![0x1000, 0x0020]);
+
+        // JSR 0x18
+        let decoded = cpu.decode_at(0x0014);
+        assert_eq!(decoded.length, 2);
+        assert_eq!(decoded.operands, vec![0x0018]);
+
+        // SHL X, 0x04
+        let decoded = cpu.decode_at(0x0018);
+        assert_eq!(
+            decoded.instruction,
+            InstructionWord::Shl {
+                a: InstructionArgumentDefinition::Register {
+                    register: Register::X
+                },
+                b: InstructionArgumentDefinition::Literal { value: 0x04 },
+            }
+        );
+        assert!(decoded.operands.is_empty());
+
+        // Nothing was executed.
+        assert_eq!(cpu.program_counter, 0x0000);
+        assert_eq!(cpu.cycles(), 0);
+    }
+
+    #[test]
+    fn decode_instruction_works() {
+        let a = InstructionArgument::Register(Register::A);
+        let cases: [(&[Word], InstructionArgument); 10] = [
+            // SET A, B
+            (&[0x0401], InstructionArgument::Register(Register::B)),
+            // SET A, [B]
+            (
+                &[0x2401],
+                InstructionArgument::AddressFromRegister(Register::B),
+            ),
+            // SET A, [0x1000+B]
+            (
+                &[0x4401, 0x1000],
+                InstructionArgument::AddressOffset {
+                    address: 0x1000,
+                    register: Register::B,
+                },
+            ),
+            // SET A, POP
+            (
+                &[0x6001],
+                InstructionArgument::StackOperation(StackOperation::Pop),
+            ),
+            // SET A, PEEK
+            (
+                &[0x6401],
+                InstructionArgument::StackOperation(StackOperation::Peek),
+            ),
+            // SET A, SP
+            (
+                &[0x6c01],
+                InstructionArgument::SpecialRegister(SpecialRegister::StackPointer),
+            ),
+            // SET A, O
+            (
+                &[0x7401],
+                InstructionArgument::SpecialRegister(SpecialRegister::Overflow),
+            ),
+            // SET A, [0x1000]
+            (&[0x7801, 0x1000], InstructionArgument::Address(0x1000)),
+            // SET A, 0x1000
+            (&[0x7c01, 0x1000], InstructionArgument::Literal(0x1000)),
+            // SET A, 0x1f
+            (&[0xfc01], InstructionArgument::Literal(0x1f)),
+        ];
+
+        for (words, b) in cases.iter() {
+            let decoded = decode_instruction(words).unwrap();
+            assert_eq!(decoded.length, words.len());
+            assert_eq!(decoded.operands, words[1..]);
+            assert_eq!(decoded.a, a);
+            assert_eq!(decoded.b, Some(*b));
+        }
+
+        // SET [0x1000], 0x20
+        let decoded = decode_instruction(&[0x7de1, 0x1000, 0x0020]).unwrap();
+        assert_eq!(decoded.a, InstructionArgument::Address(0x1000));
+        assert_eq!(decoded.b, Some(InstructionArgument::Literal(0x20)));
+
+        // SET PUSH, PC
+        let decoded = decode_instruction(&[0x71a1]).unwrap();
+        assert_eq!(
+            decoded.a,
+            InstructionArgument::StackOperation(StackOperation::Push)
+        );
+        assert_eq!(
+            decoded.b,
+            Some(InstructionArgument::SpecialRegister(
+                SpecialRegister::ProgramCounter
+            ))
+        );
+
+        // JSR 0x18
+        let decoded = decode_instruction(&[0x7c10, 0x0018]).unwrap();
+        assert_eq!(decoded.a, InstructionArgument::Literal(0x18));
+        assert_eq!(decoded.b, None);
+
+        // Missing operands.
+        assert_eq!(decode_instruction(&[]), None);
+        assert_eq!(decode_instruction(&[0x7de1, 0x1000]), None);
+        assert_eq!(decode_instruction(&[0x03e0]), None);
+    }
+
+    #[test]
+    fn addressing_modes_work() {
+        // SET [0x2000+I], [A]
+        let decoded = decode_instruction(&[0x2161, 0x2000]).unwrap();
+        assert_eq!(
+            decoded.a_mode(),
+            InstructionArgumentDefinition::AtAddressFromNextWordPlusRegister {
+                register: Register::I
+            }
+        );
+        assert_eq!(
+            decoded.b_mode(),
+            Some(InstructionArgumentDefinition::AtAddressFromRegister {
+                register: Register::A
+            })
+        );
+
+        // JSR 0x18
+        let decoded = decode_instruction(&[0x7c10, 0x0018]).unwrap();
+        assert_eq!(
+            decoded.a_mode(),
+            InstructionArgumentDefinition::NextWordLiteral
+        );
+        assert_eq!(decoded.b_mode(), None);
+    }
+
+    #[test]
+    fn decode_extended_instruction_at_works() {
+        // DVI A, 0x30
+        let cpu = DCPU16::new(&[0x03e0, 0x7c05, 0x0030]);
+        let decoded = cpu.decode_at(0x0000);
+        assert!(matches!(decoded.instruction, InstructionWord::Dvi { .. }));
+        assert_eq!(decoded.length, 3);
+        assert_eq!(decoded.operands, vec![0x0030]);
+    }
+}
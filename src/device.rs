@@ -0,0 +1,161 @@
+use crate::log::{debug, warn};
+use crate::{Register, Word, DCPU16};
+
+/// A hardware device connected to the DCPU-16.
+pub trait Device {
+    /// Gets the 32 bit hardware ID of the device.
+    fn id(&self) -> u32;
+
+    /// Gets the 16 bit version of the device.
+    fn version(&self) -> u16;
+
+    /// Gets the 32 bit manufacturer ID of the device.
+    fn manufacturer(&self) -> u32;
+
+    /// Handles a hardware interrupt sent by the CPU through `HWI`.
+    fn interrupt(&mut self, cpu: &mut DCPU16);
+
+    /// Advances the device by one step. Called before each instruction.
+    fn tick(&mut self, cpu: &mut DCPU16);
+}
+
+impl<'p> DCPU16<'p> {
+    /// Connects a hardware device to the CPU.
+    ///
+    /// Devices are enumerated in the order they were added.
+    pub fn add_device(&mut self, dev: Box<dyn Device>) {
+        debug!(
+            "Connecting device {id:08X} as device {index}",
+            id = dev.id(),
+            index = self.devices.len()
+        );
+        self.devices.push(dev);
+    }
+
+    /// Gets the number of connected devices.
+    pub(crate) fn num_devices(&self) -> Word {
+        self.devices.len() as _
+    }
+
+    /// Ticks all connected devices.
+    pub(crate) fn tick_devices(&mut self) {
+        self.with_devices(|devices, cpu| {
+            for device in devices.iter_mut() {
+                device.tick(cpu);
+            }
+        });
+    }
+
+    /// Populates `A`, `B`, `C`, `X` and `Y` with information about the specified device.
+    pub(crate) fn query_device(&mut self, index: Word) {
+        let (id, version, manufacturer) = match self.devices.get(index as usize) {
+            Some(device) => (device.id(), device.version(), device.manufacturer()),
+            None => {
+                warn!("Querying unknown device {index}", index = index);
+                (0, 0, 0)
+            }
+        };
+
+        self.set_register(Register::A, id as Word);
+        self.set_register(Register::B, (id >> 16) as Word);
+        self.set_register(Register::C, version);
+        self.set_register(Register::X, manufacturer as Word);
+        self.set_register(Register::Y, (manufacturer >> 16) as Word);
+    }
+
+    /// Sends an interrupt to the specified device.
+    pub(crate) fn interrupt_device(&mut self, index: Word) {
+        if index as usize >= self.devices.len() {
+            warn!("Interrupting unknown device {index}", index = index);
+            return;
+        }
+
+        self.with_devices(|devices, cpu| devices[index as usize].interrupt(cpu));
+    }
+
+    /// Detaches the devices from the CPU so that they can be given mutable access to it.
+    fn with_devices<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Vec<Box<dyn Device>>, &mut Self),
+    {
+        let mut devices = std::mem::take(&mut self.devices);
+        f(&mut devices, self);
+
+        // Keep devices that were connected while the others were detached.
+        let added = std::mem::replace(&mut self.devices, devices);
+        self.devices.extend(added);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A device that flips register `J` whenever it receives an interrupt.
+    struct FlipDevice;
+
+    impl Device for FlipDevice {
+        fn id(&self) -> u32 {
+            0x12345678
+        }
+
+        fn version(&self) -> u16 {
+            0x0042
+        }
+
+        fn manufacturer(&self) -> u32 {
+            0x9abcdef0
+        }
+
+        fn interrupt(&mut self, cpu: &mut DCPU16) {
+            let value = cpu.register(Register::J);
+            cpu.set_register(Register::J, !value);
+        }
+
+        fn tick(&mut self, _cpu: &mut DCPU16) {}
+    }
+
+    #[test]
+    fn hwn_works() {
+        // HWN I
+        let program = [0x1900, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.add_device(Box::new(FlipDevice));
+        cpu.add_device(Box::new(FlipDevice));
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::I), 2);
+    }
+
+    #[test]
+    fn hwq_works() {
+        // HWQ 0x00
+        let program = [0x8110, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.add_device(Box::new(FlipDevice));
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x5678);
+        assert_eq!(cpu.register(Register::B), 0x1234);
+        assert_eq!(cpu.register(Register::C), 0x0042);
+        assert_eq!(cpu.register(Register::X), 0xdef0);
+        assert_eq!(cpu.register(Register::Y), 0x9abc);
+    }
+
+    #[test]
+    fn hwi_works() {
+        // HWI 0x00
+        // HWI 0x01
+        let program = [0x8120, 0x8520, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.add_device(Box::new(FlipDevice));
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::J), 0xffff);
+
+        // Interrupts to unknown devices are ignored.
+        cpu.step();
+        assert_eq!(cpu.register(Register::J), 0xffff);
+        assert_eq!(cpu.num_devices(), 1);
+    }
+}
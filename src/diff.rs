@@ -0,0 +1,127 @@
+use crate::snapshot::CpuSnapshot;
+use crate::{Register, Word, DCPU16, NUM_REGISTERS};
+
+/// The differences between two machine states, e.g. to describe a failing emulation test.
+///
+/// All changes are given as pairs of the old value, i.e. the value of the machine the
+/// difference was computed on, and the new value, i.e. the value of the other machine.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    /// The differing registers with their old and new values.
+    pub registers: Vec<(Register, Word, Word)>,
+    /// The old and new program counter, if they differ.
+    pub program_counter: Option<(Word, Word)>,
+    /// The old and new stack pointer, if they differ.
+    pub stack_pointer: Option<(Word, Word)>,
+    /// The old and new overflow, if they differ.
+    pub overflow: Option<(Word, Word)>,
+    /// The addresses of the differing RAM cells with their old and new values.
+    pub ram: Vec<(Word, Word, Word)>,
+}
+
+/// The parts of a machine state that are compared.
+struct StateView<'a> {
+    ram: &'a [Word],
+    registers: &'a [Word; NUM_REGISTERS],
+    program_counter: Word,
+    stack_pointer: Word,
+    overflow: Word,
+}
+
+impl StateDiff {
+    /// Indicates whether both states are identical.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Compares the states. Cells beyond the end of the smaller RAM are not compared.
+    fn between(old: StateView<'_>, new: StateView<'_>) -> Self {
+        let changed = |old: Word, new: Word| if old != new { Some((old, new)) } else { None };
+
+        let registers = old
+            .registers
+            .iter()
+            .zip(new.registers.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(index, (&old, &new))| (Register::from(index as Word), old, new))
+            .collect();
+
+        let ram = old
+            .ram
+            .iter()
+            .zip(new.ram.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(address, (&old, &new))| (address as Word, old, new))
+            .collect();
+
+        Self {
+            registers,
+            program_counter: changed(old.program_counter, new.program_counter),
+            stack_pointer: changed(old.stack_pointer, new.stack_pointer),
+            overflow: changed(old.overflow, new.overflow),
+            ram,
+        }
+    }
+}
+
+impl<'p> DCPU16<'p> {
+    /// Lists the registers and RAM cells that differ from the other CPU.
+    pub fn diff(&self, other: &DCPU16) -> StateDiff {
+        StateDiff::between(self.state_view(), other.state_view())
+    }
+
+    /// Gets the parts of the machine state that are compared.
+    fn state_view(&self) -> StateView<'_> {
+        StateView {
+            ram: &self.ram,
+            registers: &self.registers,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            overflow: self.overflow,
+        }
+    }
+}
+
+impl CpuSnapshot {
+    /// Lists the registers and RAM cells that differ from the other snapshot.
+    pub fn diff(&self, other: &CpuSnapshot) -> StateDiff {
+        StateDiff::between(self.state_view(), other.state_view())
+    }
+
+    /// Gets the parts of the machine state that are compared.
+    fn state_view(&self) -> StateView<'_> {
+        StateView {
+            ram: &self.ram,
+            registers: &self.registers,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            overflow: self.overflow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_works() {
+        let program = [0x7c01, 0x0030];
+        let cpu = DCPU16::new(&program);
+        let mut other = DCPU16::new(&program);
+        assert!(cpu.diff(&other).is_empty());
+
+        other.set_register(Register::C, 0x1234);
+        other.set_ram(0x1000, 0x0020);
+
+        let diff = cpu.diff(&other);
+        assert_eq!(diff.registers, vec![(Register::C, 0x0000, 0x1234)]);
+        assert_eq!(diff.ram, vec![(0x1000, 0x0000, 0x0020)]);
+        assert_eq!(diff.program_counter, None);
+        assert_eq!(diff.stack_pointer, None);
+        assert_eq!(diff.overflow, None);
+        assert_eq!(cpu.snapshot().diff(&other.snapshot()), diff);
+    }
+}
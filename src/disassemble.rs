@@ -1,12 +1,18 @@
+use crate::disassembler::{DisassemblyOptions, LiteralFormat, OverflowRegisterName};
 use crate::instruction_word::{InstructionWord, NonBasicInstruction};
 use crate::instruction::{InstructionWithOperands, ResolvedValue};
 use crate::instruction_argument::{InstructionArgument, InstructionArgumentDefinition};
-use crate::Register;
+use crate::{Register, Word};
 
 pub trait Disassemble {
     /// Gets the mnemonic for the given instruction.
     fn disassemble(&self) -> String;
 
+    /// Gets the mnemonic for the given instruction, rendered with the specified options.
+    fn disassemble_with(&self, _options: &DisassemblyOptions) -> String {
+        self.disassemble()
+    }
+
     /// Gets a human-readable string for the given instruction.
     fn disassemble_human(&self) -> String {
         self.disassemble()
@@ -15,16 +21,7 @@ pub trait Disassemble {
 
 impl Disassemble for Register {
     fn disassemble(&self) -> String {
-        match self {
-            Register::A => String::from("A"),
-            Register::B => String::from("B"),
-            Register::C => String::from("C"),
-            Register::X => String::from("X"),
-            Register::Y => String::from("Y"),
-            Register::Z => String::from("Z"),
-            Register::I => String::from("I"),
-            Register::J => String::from("J"),
-        }
+        self.to_string()
     }
 }
 
@@ -32,18 +29,18 @@ impl Disassemble for ResolvedValue {
     fn disassemble(&self) -> String {
         match self.argument_definition {
             InstructionArgumentDefinition::Register { register } => register.disassemble(),
-            InstructionArgumentDefinition::Literal { value } => String::from(format!("0x{:02X}", value)),
-            InstructionArgumentDefinition::NextWordLiteral => String::from(format!("0x{:02X}", self.resolved_value)),
-            InstructionArgumentDefinition::AtAddressFromNextWord => String::from(format!(
+            InstructionArgumentDefinition::Literal { value } => format!("0x{:02X}", value),
+            InstructionArgumentDefinition::NextWordLiteral => format!("0x{:02X}", self.resolved_value),
+            InstructionArgumentDefinition::AtAddressFromNextWord => format!(
                 "[0x{:02X}]",
                 self.argument.get_literal().unwrap()
-            )),
+            ),
             InstructionArgumentDefinition::OfOverflow => String::from("O"),
             InstructionArgumentDefinition::OfProgramCounter => String::from("PC"),
             InstructionArgumentDefinition::OfStackPointer => String::from("SP"),
             InstructionArgumentDefinition::AtAddressFromNextWordPlusRegister { .. } => match self.argument {
                 InstructionArgument::AddressOffset { address, register } => {
-                    String::from(format!("[0x{:02X}+{}]", address, register.disassemble()))
+                    format!("[0x{:02X}+{}]", address, register.disassemble())
                 }
                 _ => unreachable!(),
             },
@@ -51,30 +48,34 @@ impl Disassemble for ResolvedValue {
             InstructionArgumentDefinition::Peek => String::from("PEEK"),
             InstructionArgumentDefinition::Push => String::from("PUSH"),
             InstructionArgumentDefinition::AtAddressFromRegister { register } => {
-                String::from(format!("[{}]", register.disassemble()))
+                format!("[{}]", register.disassemble())
             }
         }
     }
 
+    fn disassemble_with(&self, options: &DisassemblyOptions) -> String {
+        disassemble_operand(self, options, false)
+    }
+
     fn disassemble_human(&self) -> String {
         match self.argument_definition {
-            InstructionArgumentDefinition::AtAddressFromNextWord => String::from(format!(
+            InstructionArgumentDefinition::AtAddressFromNextWord => format!(
                 "RAM[0x{:02X}]",
                 self.argument.get_literal().unwrap()
-            )),
+            ),
             // Value::OfOverflow => String::from("O"),
             // Value::OfProgramCounter => String::from("PC"),
             // Value::OfStackPointer => String::from("SP"),
             InstructionArgumentDefinition::AtAddressFromNextWordPlusRegister { .. } => match self.argument {
-                InstructionArgument::AddressOffset { address, register } => String::from(format!(
+                InstructionArgument::AddressOffset { address, register } => format!(
                     "RAM[0x{:02X} + {}]",
                     address,
                     register.disassemble_human()
-                )),
+                ),
                 _ => unreachable!(),
             },
             InstructionArgumentDefinition::AtAddressFromRegister { register } => {
-                String::from(format!("RAM[{}]", register.disassemble_human()))
+                format!("RAM[{}]", register.disassemble_human())
             }
             InstructionArgumentDefinition::Pop => String::from("pop value from stack"),
             InstructionArgumentDefinition::Peek => String::from("current stack value"),
@@ -87,173 +88,379 @@ impl Disassemble for ResolvedValue {
 impl Disassemble for InstructionWithOperands {
     fn disassemble(&self) -> String {
         match self.instruction {
-            InstructionWord::Set { .. } => String::from(format!(
+            InstructionWord::Set { .. } => format!(
                 "SET {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Add { .. } => String::from(format!(
+            ),
+            InstructionWord::Add { .. } => format!(
                 "ADD {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Sub { .. } => String::from(format!(
+            ),
+            InstructionWord::Sub { .. } => format!(
                 "SUB {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Mul { .. } => String::from(format!(
+            ),
+            InstructionWord::Mul { .. } => format!(
                 "MUL {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Div { .. } => String::from(format!(
+            ),
+            InstructionWord::Div { .. } => format!(
                 "DIV {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Mod { .. } => String::from(format!(
+            ),
+            InstructionWord::Mod { .. } => format!(
                 "MOD {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Shl { .. } => String::from(format!(
+            ),
+            InstructionWord::Shl { .. } => format!(
                 "SHL {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Shr { .. } => String::from(format!(
+            ),
+            InstructionWord::Shr { .. } => format!(
                 "SHR {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::And { .. } => String::from(format!(
+            ),
+            InstructionWord::And { .. } => format!(
                 "AND {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Bor { .. } => String::from(format!(
+            ),
+            InstructionWord::Bor { .. } => format!(
                 "BOR {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Xor { .. } => String::from(format!(
+            ),
+            InstructionWord::Xor { .. } => format!(
                 "XOR {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Ife { .. } => String::from(format!(
+            ),
+            InstructionWord::Ife { .. } => format!(
                 "IFE {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Ifn { .. } => String::from(format!(
+            ),
+            InstructionWord::Ifn { .. } => format!(
                 "IFN {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Ifg { .. } => String::from(format!(
+            ),
+            InstructionWord::Ifg { .. } => format!(
                 "IFG {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
-            InstructionWord::Ifb { .. } => String::from(format!(
+            ),
+            InstructionWord::Ifb { .. } => format!(
                 "IFB {}, {}",
                 self.a.disassemble(),
                 self.b.expect("require second argument").disassemble()
-            )),
+            ),
+            InstructionWord::Mli { .. } => format!(
+                "MLI {}, {}",
+                self.a.disassemble(),
+                self.b.expect("require second argument").disassemble()
+            ),
+            InstructionWord::Dvi { .. } => format!(
+                "DVI {}, {}",
+                self.a.disassemble(),
+                self.b.expect("require second argument").disassemble()
+            ),
+            InstructionWord::Sti { .. } => format!(
+                "STI {}, {}",
+                self.a.disassemble(),
+                self.b.expect("require second argument").disassemble()
+            ),
+            InstructionWord::Std { .. } => format!(
+                "STD {}, {}",
+                self.a.disassemble(),
+                self.b.expect("require second argument").disassemble()
+            ),
+            InstructionWord::Ifa { .. } => format!(
+                "IFA {}, {}",
+                self.a.disassemble(),
+                self.b.expect("require second argument").disassemble()
+            ),
+            InstructionWord::Ifl { .. } => format!(
+                "IFL {}, {}",
+                self.a.disassemble(),
+                self.b.expect("require second argument").disassemble()
+            ),
+            InstructionWord::Ifu { .. } => format!(
+                "IFU {}, {}",
+                self.a.disassemble(),
+                self.b.expect("require second argument").disassemble()
+            ),
             InstructionWord::NonBasic(nbi) => match nbi {
-                NonBasicInstruction::Reserved => panic!(),
+                NonBasicInstruction::Reserved => String::from("RESERVED"),
                 NonBasicInstruction::Jsr { .. } => {
-                    String::from(format!("JSR {}", self.a.disassemble()))
+                    format!("JSR {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Int { .. } => {
+                    format!("INT {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Iag { .. } => {
+                    format!("IAG {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Ias { .. } => {
+                    format!("IAS {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Rfi { .. } => {
+                    format!("RFI {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Iaq { .. } => {
+                    format!("IAQ {}", self.a.disassemble())
                 }
+                NonBasicInstruction::Hwn { .. } => {
+                    format!("HWN {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Hwq { .. } => {
+                    format!("HWQ {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Hwi { .. } => {
+                    format!("HWI {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Hlt { .. } => {
+                    format!("HLT {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Extended => String::from("EXTENDED"),
             },
         }
     }
 
+    fn disassemble_with(&self, options: &DisassemblyOptions) -> String {
+        let mnemonic = self.disassemble();
+        if options.overflow_name == OverflowRegisterName::O
+            && options.literal_format == LiteralFormat::Hex
+            && options.symbols.is_empty()
+        {
+            return mnemonic;
+        }
+
+        // Short literals are only named when used as jump targets, since small
+        // numbers would otherwise frequently be mistaken for labels.
+        let operation = mnemonic.split(' ').next().unwrap_or_default();
+        match (self.instruction, self.b) {
+            (InstructionWord::NonBasic(nbi), _) => {
+                let is_jump = matches!(nbi, NonBasicInstruction::Jsr { .. });
+                format!(
+                    "{} {}",
+                    operation,
+                    disassemble_operand(&self.a, options, is_jump)
+                )
+            }
+            (_, Some(b)) => {
+                let is_jump = matches!(self.instruction, InstructionWord::Set { .. })
+                    && self.a.argument_definition
+                        == InstructionArgumentDefinition::OfProgramCounter;
+                format!(
+                    "{} {}, {}",
+                    operation,
+                    disassemble_operand(&self.a, options, false),
+                    disassemble_operand(&b, options, is_jump)
+                )
+            }
+            (_, None) => mnemonic,
+        }
+    }
+
     fn disassemble_human(&self) -> String {
         match self.instruction {
-            InstructionWord::Set { .. } => String::from(format!(
+            InstructionWord::Set { .. } => format!(
                 "{0} <- {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Add { .. } => String::from(format!(
+            ),
+            InstructionWord::Add { .. } => format!(
                 "{0} <- {0} + {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Sub { .. } => String::from(format!(
+            ),
+            InstructionWord::Sub { .. } => format!(
                 "{0} <- {0} - {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Mul { .. } => String::from(format!(
+            ),
+            InstructionWord::Mul { .. } => format!(
                 "{0} <- {0} * {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Div { .. } => String::from(format!(
+            ),
+            InstructionWord::Div { .. } => format!(
                 "{0} <- {0} / {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Mod { .. } => String::from(format!(
+            ),
+            InstructionWord::Mod { .. } => format!(
                 "{0} <- {0} % {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Shl { .. } => String::from(format!(
+            ),
+            InstructionWord::Shl { .. } => format!(
                 "{0} <- {0} << {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Shr { .. } => String::from(format!(
+            ),
+            InstructionWord::Shr { .. } => format!(
                 "{0} <- {0} >> {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::And { .. } => String::from(format!(
+            ),
+            InstructionWord::And { .. } => format!(
                 "{0} <- {0} & {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Bor { .. } => String::from(format!(
+            ),
+            InstructionWord::Bor { .. } => format!(
                 "{0} <- {0} | {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Xor { .. } => String::from(format!(
+            ),
+            InstructionWord::Xor { .. } => format!(
                 "{0} <- {0} ^ {1}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Ife { .. } => String::from(format!(
+            ),
+            InstructionWord::Ife { .. } => format!(
                 "execute next instruction if {} == {}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Ifn { .. } => String::from(format!(
+            ),
+            InstructionWord::Ifn { .. } => format!(
                 "execute next instruction if {} != {}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Ifg { .. } => String::from(format!(
+            ),
+            InstructionWord::Ifg { .. } => format!(
                 "execute next instruction if {} > {}",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
-            InstructionWord::Ifb { .. } => String::from(format!(
+            ),
+            InstructionWord::Ifb { .. } => format!(
                 "execute next instruction if ({} & {}) != 0",
                 self.a.disassemble_human(),
                 self.b.expect("require second argument").disassemble_human()
-            )),
+            ),
+            InstructionWord::Mli { .. } => format!(
+                "{0} <- {0} * {1} (signed)",
+                self.a.disassemble_human(),
+                self.b.expect("require second argument").disassemble_human()
+            ),
+            InstructionWord::Dvi { .. } => format!(
+                "{0} <- {0} / {1} (signed)",
+                self.a.disassemble_human(),
+                self.b.expect("require second argument").disassemble_human()
+            ),
+            InstructionWord::Sti { .. } => format!(
+                "{} <- {}, I <- I + 1, J <- J + 1",
+                self.a.disassemble_human(),
+                self.b.expect("require second argument").disassemble_human()
+            ),
+            InstructionWord::Std { .. } => format!(
+                "{} <- {}, I <- I - 1, J <- J - 1",
+                self.a.disassemble_human(),
+                self.b.expect("require second argument").disassemble_human()
+            ),
+            InstructionWord::Ifa { .. } => format!(
+                "execute next instruction if {} > {} (signed)",
+                self.a.disassemble_human(),
+                self.b.expect("require second argument").disassemble_human()
+            ),
+            InstructionWord::Ifl { .. } => format!(
+                "execute next instruction if {} < {}",
+                self.a.disassemble_human(),
+                self.b.expect("require second argument").disassemble_human()
+            ),
+            InstructionWord::Ifu { .. } => format!(
+                "execute next instruction if {} < {} (signed)",
+                self.a.disassemble_human(),
+                self.b.expect("require second argument").disassemble_human()
+            ),
             InstructionWord::NonBasic(nbi) => match nbi {
-                NonBasicInstruction::Reserved => panic!(),
+                NonBasicInstruction::Reserved => String::from("reserved instruction"),
                 NonBasicInstruction::Jsr { .. } => {
-                    String::from(format!("jump to subroutine at {}", self.a.disassemble()))
+                    format!("jump to subroutine at {}", self.a.disassemble())
+                }
+                NonBasicInstruction::Int { .. } => {
+                    format!("trigger interrupt {}", self.a.disassemble_human())
+                }
+                NonBasicInstruction::Iag { .. } => {
+                    format!("{} <- IA", self.a.disassemble_human())
+                }
+                NonBasicInstruction::Ias { .. } => {
+                    format!("IA <- {}", self.a.disassemble_human())
+                }
+                NonBasicInstruction::Rfi { .. } => String::from("return from interrupt"),
+                NonBasicInstruction::Iaq { .. } => {
+                    format!("queue interrupts if {} != 0", self.a.disassemble_human())
+                }
+                NonBasicInstruction::Hwn { .. } => {
+                    format!("{} <- number of devices", self.a.disassemble_human())
+                }
+                NonBasicInstruction::Hwq { .. } => {
+                    format!("query device {}", self.a.disassemble_human())
+                }
+                NonBasicInstruction::Hwi { .. } => {
+                    format!("send interrupt to device {}", self.a.disassemble_human())
                 }
+                NonBasicInstruction::Hlt { .. } => String::from("halt"),
+                NonBasicInstruction::Extended => String::from("extended instruction prefix"),
             },
         }
     }
 }
+
+/// Disassembles an operand, rendering it with the specified options.
+fn disassemble_operand(
+    value: &ResolvedValue,
+    options: &DisassemblyOptions,
+    is_jump_target: bool,
+) -> String {
+    let symbol = |address: Word| options.symbols.get(&address);
+    let literal = |literal: Word| match options.literal_format {
+        LiteralFormat::Hex => value.disassemble(),
+        LiteralFormat::SignedDecimal => (literal as i16).to_string(),
+    };
+    match (value.argument_definition, value.argument) {
+        (InstructionArgumentDefinition::OfOverflow, _)
+            if options.overflow_name == OverflowRegisterName::Ex =>
+        {
+            String::from("EX")
+        }
+        (InstructionArgumentDefinition::NextWordLiteral, InstructionArgument::Literal(word)) => {
+            match symbol(word) {
+                Some(name) => name.clone(),
+                None => literal(word),
+            }
+        }
+        (InstructionArgumentDefinition::Literal { value: word }, _) if is_jump_target => {
+            match symbol(word) {
+                Some(name) => name.clone(),
+                None => literal(word),
+            }
+        }
+        (InstructionArgumentDefinition::Literal { value: word }, _) => literal(word),
+        (
+            InstructionArgumentDefinition::AtAddressFromNextWord,
+            InstructionArgument::Address(address),
+        ) => match symbol(address) {
+            Some(name) => format!("[{}]", name),
+            None => value.disassemble(),
+        },
+        (
+            InstructionArgumentDefinition::AtAddressFromNextWordPlusRegister { .. },
+            InstructionArgument::AddressOffset { address, register },
+        ) => match symbol(address) {
+            Some(name) => format!("[{}+{}]", name, register.disassemble()),
+            None => value.disassemble(),
+        },
+        _ => value.disassemble(),
+    }
+}
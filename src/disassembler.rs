@@ -0,0 +1,439 @@
+//! Disassembly of DCPU-16 program bytecode.
+
+use crate::disassemble::Disassemble;
+use crate::instruction::{Instruction, InstructionWithOperands};
+use crate::instruction_word::{InstructionWord, NonBasicInstruction, EXTENDED_PREFIX};
+use crate::{Decode, Word};
+use std::collections::HashMap;
+
+/// The width of the raw words column of a listing, i.e. three words separated by spaces.
+const RAW_WORDS_COLUMN_WIDTH: usize = 3 * 4 + 2;
+
+/// The name used for the overflow register.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowRegisterName {
+    /// `O`, as in the 1.1 specification.
+    #[default]
+    O,
+    /// `EX`, as in later revisions of the specification.
+    Ex,
+}
+
+/// The format used for literal values.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LiteralFormat {
+    /// Hexadecimal, e.g. `0xFFFF`.
+    #[default]
+    Hex,
+    /// Signed decimal, e.g. `-1`. Addresses are still rendered in hexadecimal.
+    SignedDecimal,
+}
+
+/// Controls how [`disassemble_program`] and [`disassemble_listing`] render a program.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DisassemblyOptions {
+    /// The name used for the overflow register.
+    pub overflow_name: OverflowRegisterName,
+    /// The names of known addresses, e.g. labels. Addresses and jump targets that match a
+    /// known address are rendered by the name of the symbol, e.g. `SET PC, crash`.
+    ///
+    /// The symbols map addresses to names, i.e. the label map of
+    /// [`assemble_with_symbols`](crate::assemble_with_symbols) needs to be inverted.
+    pub symbols: HashMap<Word, String>,
+    /// The format used for literal values.
+    pub literal_format: LiteralFormat,
+    /// The `(start, len)` regions whose words are emitted as `DAT` entries instead of being
+    /// decoded, sorted by their start address.
+    ///
+    /// An instruction that would overlap a data region is emitted as `DAT` entries as well;
+    /// decoding resumes after each region.
+    pub data_regions: Vec<(Word, Word)>,
+}
+
+/// A disassembled instruction, or a word that could not be decoded.
+struct Entry<'w> {
+    /// The address of the first word.
+    address: usize,
+    /// The raw words of the instruction.
+    words: &'w [Word],
+    /// The mnemonic of the instruction.
+    mnemonic: String,
+}
+
+/// Disassembles the program into pairs of instruction addresses and mnemonics.
+///
+/// Words that do not form a valid instruction, i.e. reserved instructions, extended prefixes
+/// without a valid extended instruction and a trailing truncated instruction, are emitted
+/// as `DAT` entries.
+pub fn disassemble_program(words: &[u16], options: &DisassemblyOptions) -> Vec<(u16, String)> {
+    program_from_entries(disassemble_entries(words, options))
+}
+
+/// Converts the entries into pairs of instruction addresses and mnemonics.
+fn program_from_entries(entries: Vec<Entry<'_>>) -> Vec<(u16, String)> {
+    entries
+        .into_iter()
+        .map(|entry| (entry.address as Word, entry.mnemonic))
+        .collect()
+}
+
+/// Disassembles the program into a listing of addresses, raw words and mnemonics,
+/// with one instruction per line, e.g.
+///
+/// ```text
+/// 0000: 7c01 0030       SET A, 0x30
+/// 0002: 7de1 1000 0020  SET [0x1000], 0x20
+/// ```
+///
+/// Words that do not form a valid instruction are shown as `DAT` entries.
+pub fn disassemble_listing(words: &[u16], options: &DisassemblyOptions) -> String {
+    listing_from_entries(disassemble_entries(words, options))
+}
+
+/// Renders the entries as a listing of addresses, raw words and mnemonics.
+fn listing_from_entries(entries: Vec<Entry<'_>>) -> String {
+    let mut listing = String::new();
+    for entry in entries {
+        let raw_words: Vec<String> = entry
+            .words
+            .iter()
+            .map(|word| format!("{:04x}", word))
+            .collect();
+        listing.push_str(&format!(
+            "{:04x}: {:<width$}  {}\n",
+            entry.address,
+            raw_words.join(" "),
+            entry.mnemonic,
+            width = RAW_WORDS_COLUMN_WIDTH
+        ));
+    }
+    listing
+}
+
+/// Walks the program and disassembles each instruction.
+fn disassemble_entries<'w>(words: &'w [u16], options: &DisassemblyOptions) -> Vec<Entry<'w>> {
+    let mut entries = Vec::new();
+    let mut data_regions = options.data_regions.iter().peekable();
+
+    let mut address = 0;
+    while address < words.len() {
+        while let Some(&&(start, len)) = data_regions.peek() {
+            if start as usize + len as usize > address {
+                break;
+            }
+            data_regions.next();
+        }
+
+        // Instructions end before the next data region.
+        let end = match data_regions.peek() {
+            Some(&&(start, _)) if start as usize <= address => {
+                entries.push(disassemble_data(words, address));
+                address += 1;
+                continue;
+            }
+            Some(&&(start, _)) => words.len().min(start as usize),
+            None => words.len(),
+        };
+
+        let mut raw_instruction = words[address];
+        let mut instruction = InstructionWord::decode(raw_instruction);
+        if raw_instruction == EXTENDED_PREFIX && address + 1 < end {
+            raw_instruction = words[address + 1];
+            instruction = InstructionWord::decode_extended(raw_instruction);
+        }
+        let length = instruction.length_in_words();
+        let prefix = instruction.num_prefix_words();
+
+        if matches!(
+            instruction,
+            InstructionWord::NonBasic(NonBasicInstruction::Reserved)
+                | InstructionWord::NonBasic(NonBasicInstruction::Extended)
+        ) {
+            entries.push(disassemble_data(words, address));
+            address += 1;
+            continue;
+        }
+
+        // The operands of the last instruction may be missing, or overlap data.
+        if address + length > end {
+            for offset in address..end {
+                entries.push(disassemble_data(words, offset));
+            }
+            address = end;
+            continue;
+        }
+
+        let operands = address + prefix + 1;
+        let instruction = match length - prefix {
+            1 => Instruction::OneWord {
+                instruction,
+                raw_instruction,
+            },
+            2 => Instruction::TwoWord {
+                instruction,
+                raw_instruction,
+                raw_1st: words[operands],
+            },
+            3 => Instruction::ThreeWord {
+                instruction,
+                raw_instruction,
+                raw_1st: words[operands],
+                raw_2nd: words[operands + 1],
+            },
+            _ => unreachable!(),
+        };
+
+        let instruction = InstructionWithOperands::resolve_static(instruction);
+        entries.push(Entry {
+            address,
+            words: &words[address..address + length],
+            mnemonic: instruction.disassemble_with(options),
+        });
+        address += length;
+    }
+
+    entries
+}
+
+/// Renders the raw word at the specified address as a `DAT` entry.
+fn disassemble_data(words: &[u16], address: usize) -> Entry<'_> {
+    Entry {
+        address,
+        words: &words[address..=address],
+        mnemonic: format!("DAT 0x{:04X}", words[address]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassemble_sample_program_works() {
+        let program = [
+            0x7c01, 0x0030, 0x7de1, 0x1000, 0x0020, 0x7803, 0x1000, 0xc00d, 0x7dc1, 0x001a, 0xa861,
+            0x7c01, 0x2000, 0x2161, 0x2000, 0x8463, 0x806d, 0x7dc1, 0x000d, 0x9031, 0x7c10, 0x0018,
+            0x7dc1, 0x001a, 0x9037, 0x61c1, 0x7dc1, 0x001a,
+        ];
+
+        let listing = disassemble_program(&program, &DisassemblyOptions::default());
+        let expected = [
+            (0x0000, "SET A, 0x30"),
+            (0x0002, "SET [0x1000], 0x20"),
+            (0x0005, "SUB A, [0x1000]"),
+            (0x0007, "IFN A, 0x10"),
+            (0x0008, "SET PC, 0x1A"),
+            (0x000A, "SET I, 0x0A"),
+            (0x000B, "SET A, 0x2000"),
+            (0x000D, "SET [0x2000+I], [A]"),
+            (0x000F, "SUB I, 0x01"),
+            (0x0010, "IFN I, 0x00"),
+            (0x0011, "SET PC, 0x0D"),
+            (0x0013, "SET X, 0x04"),
+            (0x0014, "JSR 0x18"),
+            (0x0016, "SET PC, 0x1A"),
+            (0x0018, "SHL X, 0x04"),
+            (0x0019, "SET PC, POP"),
+            (0x001A, "SET PC, 0x1A"),
+        ];
+
+        assert_eq!(listing.len(), expected.len());
+        for ((address, mnemonic), (expected_address, expected_mnemonic)) in
+            listing.iter().zip(expected.iter())
+        {
+            assert_eq!(address, expected_address);
+            assert_eq!(mnemonic, expected_mnemonic);
+        }
+    }
+
+    #[test]
+    fn disassemble_truncated_instruction_works() {
+        // A reserved instruction, then SET [0x1000], 0x20 without its literal.
+        let program = [0x0000, 0x7de1, 0x1000];
+        let listing = disassemble_program(&program, &DisassemblyOptions::default());
+        assert_eq!(
+            listing,
+            vec![
+                (0x0000, String::from("DAT 0x0000")),
+                (0x0001, String::from("DAT 0x7DE1")),
+                (0x0002, String::from("DAT 0x1000")),
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_extended_instruction_works() {
+        // DVI A, 0x30
+        // (extended prefix of a reserved instruction)
+        // (extended prefix without instruction)
+        let program = [0x03e0, 0x7c05, 0x0030, 0x03e0, 0x0000, 0x03e0];
+        let expected = "\
+0000: 03e0 7c05 0030  DVI A, 0x30
+0003: 03e0            DAT 0x03E0
+0004: 0000            DAT 0x0000
+0005: 03e0            DAT 0x03E0
+";
+        assert_eq!(
+            disassemble_listing(&program, &DisassemblyOptions::default()),
+            expected
+        );
+    }
+
+    #[test]
+    fn disassemble_overflow_register_works() {
+        // SET O, 0x01
+        // SET A, O
+        let program = [0x85d1, 0x7401];
+        assert_eq!(
+            disassemble_program(&program, &DisassemblyOptions::default()),
+            vec![
+                (0x0000, String::from("SET O, 0x01")),
+                (0x0001, String::from("SET A, O")),
+            ]
+        );
+        assert_eq!(
+            disassemble_program(
+                &program,
+                &DisassemblyOptions {
+                    overflow_name: OverflowRegisterName::Ex,
+                    ..DisassemblyOptions::default()
+                }
+            ),
+            vec![
+                (0x0000, String::from("SET EX, 0x01")),
+                (0x0001, String::from("SET A, EX")),
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_signed_literals_works() {
+        // SET A, 0xFFFF
+        // SET [0xFFFE], 0x10
+        let program = [0x7c01, 0xffff, 0xc1e1, 0xfffe];
+        assert_eq!(
+            disassemble_program(&program, &DisassemblyOptions::default()),
+            vec![
+                (0x0000, String::from("SET A, 0xFFFF")),
+                (0x0002, String::from("SET [0xFFFE], 0x10")),
+            ]
+        );
+        assert_eq!(
+            disassemble_program(
+                &program,
+                &DisassemblyOptions {
+                    literal_format: LiteralFormat::SignedDecimal,
+                    ..DisassemblyOptions::default()
+                }
+            ),
+            vec![
+                (0x0000, String::from("SET A, -1")),
+                (0x0002, String::from("SET [0xFFFE], 16")),
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_with_symbols_works() {
+        // :start SET [data], 0x20
+        // SET PC, start
+        // JSR sub
+        // :sub SET A, [data+I]
+        // (data at 0x0008)
+        let program = [0x7de1, 0x0008, 0x0020, 0x81c1, 0x9410, 0x5801, 0x0008];
+        let options = DisassemblyOptions {
+            symbols: [(0x0000, "start"), (0x0005, "sub"), (0x0008, "data")]
+                .iter()
+                .map(|(address, name)| (*address, String::from(*name)))
+                .collect(),
+            ..DisassemblyOptions::default()
+        };
+
+        let listing = disassemble_program(&program, &options);
+        assert_eq!(
+            listing,
+            vec![
+                (0x0000, String::from("SET [data], 0x20")),
+                (0x0003, String::from("SET PC, start")),
+                (0x0004, String::from("JSR sub")),
+                (0x0005, String::from("SET A, [data+I]")),
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_data_regions_works() {
+        // SET A, table
+        // SET PC, POP
+        // :table DAT 0x7C01, 0x0030, 0x0000
+        // SET A, 0x01
+        let program = [0x7c01, 0x0003, 0x61c1, 0x7c01, 0x0030, 0x0000, 0x8401];
+        let expected = "\
+0000: 7c01 0003       SET A, 0x03
+0002: 61c1            SET PC, POP
+0003: 7c01            DAT 0x7C01
+0004: 0030            DAT 0x0030
+0005: 0000            DAT 0x0000
+0006: 8401            SET A, 0x01
+";
+        let options = DisassemblyOptions {
+            data_regions: vec![(0x0003, 3)],
+            ..DisassemblyOptions::default()
+        };
+        assert_eq!(disassemble_listing(&program, &options), expected);
+
+        // Data regions combine with the other options.
+        let options = DisassemblyOptions {
+            symbols: [(0x0003, String::from("table"))].iter().cloned().collect(),
+            ..options
+        };
+        let listing = disassemble_program(&program, &options);
+        assert_eq!(listing[0], (0x0000, String::from("SET A, table")));
+        assert_eq!(listing[2], (0x0003, String::from("DAT 0x7C01")));
+
+        // Without the region, the table is decoded as an instruction.
+        assert_eq!(
+            disassemble_program(&program, &DisassemblyOptions::default())[2],
+            (0x0003, String::from("SET A, 0x30"))
+        );
+
+        // Data takes precedence over an overlapping instruction.
+        assert_eq!(
+            disassemble_program(
+                &program,
+                &DisassemblyOptions {
+                    data_regions: vec![(0x0001, 1), (0x0004, 2)],
+                    ..DisassemblyOptions::default()
+                }
+            ),
+            vec![
+                (0x0000, String::from("DAT 0x7C01")),
+                (0x0001, String::from("DAT 0x0003")),
+                (0x0002, String::from("SET PC, POP")),
+                (0x0003, String::from("DAT 0x7C01")),
+                (0x0004, String::from("DAT 0x0030")),
+                (0x0005, String::from("DAT 0x0000")),
+                (0x0006, String::from("SET A, 0x01")),
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_listing_works() {
+        // SET A, 0x30
+        // SET [0x1000], 0x20
+        // SET PC, POP
+        // (reserved)
+        let program = [0x7c01, 0x0030, 0x7de1, 0x1000, 0x0020, 0x61c1, 0x0000];
+        let expected = "\
+0000: 7c01 0030       SET A, 0x30
+0002: 7de1 1000 0020  SET [0x1000], 0x20
+0005: 61c1            SET PC, POP
+0006: 0000            DAT 0x0000
+";
+        assert_eq!(
+            disassemble_listing(&program, &DisassemblyOptions::default()),
+            expected
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use crate::disassemble::Disassemble;
-use crate::instruction_word::{InstructionWord};
+use crate::instruction_word::{InstructionWord, EXTENDED_PREFIX};
 use crate::instruction_argument::{InstructionArgumentDefinition, InstructionArgument};
 use crate::{Word, DCPU16};
 use std::fmt::{Debug, Formatter};
 
 /// A decoded instruction with all extra operands.
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Instruction {
     /// An instruction that has one word, i.e., does not take extra operands.
@@ -37,6 +38,33 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    /// Interprets the arguments of the instruction word, assigning the extra operands
+    /// to the "a" and "b" values, without reading any machine state.
+    pub fn decode_arguments(&self) -> (InstructionArgument, Option<InstructionArgument>) {
+        let (_, instruction_word, raw_1st, raw_2nd) = self.unpack();
+        let (a, b) = instruction_word.unpack();
+
+        // The "a" value may use an "inline" value, e.g. a register or default literal.
+        // In that case the first operand really belongs to the "b" value.
+        match b {
+            Some(b) if a.has_extra_words() => (
+                InstructionArgument::from(a, raw_1st),
+                Some(InstructionArgument::from(b, raw_2nd)),
+            ),
+            Some(b) => {
+                assert!(raw_2nd.is_none());
+                (
+                    InstructionArgument::from(a, None),
+                    Some(InstructionArgument::from(b, raw_1st)),
+                )
+            }
+            None => {
+                assert!(raw_2nd.is_none());
+                (InstructionArgument::from(a, raw_1st), None)
+            }
+        }
+    }
+
     /// Extracts the values of the instruction into a tuple.
     pub fn unpack(&self) -> (Word, InstructionWord, Option<Word>, Option<Word>) {
         match self {
@@ -76,73 +104,49 @@ pub struct InstructionWithOperands {
 impl InstructionWithOperands {
     /// Resolves the values for each argument of the instruction word.
     pub fn resolve(cpu: &mut DCPU16, instruction: Instruction) -> Self {
-        let (raw_instruction, instruction_word, raw_1st, raw_2nd) = instruction.unpack();
+        Self::resolve_with(instruction, |argument| cpu.read_value(argument))
+    }
 
-        // Get the "a" and "b" value definitions from the original instruction.
+    /// Interprets the arguments of the instruction word without reading any machine state.
+    ///
+    /// Literal values resolve to themselves, all other values resolve to zero.
+    pub fn resolve_static(instruction: Instruction) -> Self {
+        Self::resolve_with(instruction, |argument| match argument {
+            InstructionArgument::Literal(value) => value,
+            _ => 0,
+        })
+    }
+
+    /// Resolves the values for each argument of the instruction word using the specified function.
+    ///
+    /// The "a" value is always resolved before the "b" value.
+    fn resolve_with<F>(instruction: Instruction, mut resolve_argument: F) -> Self
+    where
+        F: FnMut(InstructionArgument) -> Word,
+    {
+        let (raw_instruction, instruction_word, _, _) = instruction.unpack();
         let (a, b) = instruction_word.unpack();
+        let (lhs_arg, rhs_arg) = instruction.decode_arguments();
 
-        // Most instructions have two operands.
-        if let Some(b) = b {
-            // The "a" value always exists, however it may use an "inline" value, e.g. a
-            // register or default literal. In that case the "first operand" provided to the
-            // instruction really belongs to the second value, i.e., "b".
-            if a.has_extra_words() {
-                let (lhs_arg, lhs) = cpu.resolve_argument(a, raw_1st);
-                let (rhs_arg, rhs) = cpu.resolve_argument(b, raw_2nd);
-
-                InstructionWithOperands {
-                    raw_instruction,
-                    instruction: instruction_word,
-                    a: ResolvedValue {
-                        argument_definition: a,
-                        argument: lhs_arg,
-                        resolved_value: lhs
-                    },
-                    b: Some(ResolvedValue {
-                        argument_definition: b,
-                        argument: rhs_arg,
-                        resolved_value: rhs
-                    }),
-                }
-            }
-            else {
-                // Since we know that the "a" value has no extra operand, we pass it to the second.
-                let (lhs_arg, lhs) = cpu.resolve_argument(a, None);
-                let (rhs_arg, rhs) = cpu.resolve_argument(b, raw_1st);
-                assert!(raw_2nd.is_none());
+        let lhs = resolve_argument(lhs_arg);
+        let b = match (b, rhs_arg) {
+            (Some(b), Some(rhs_arg)) => Some(ResolvedValue {
+                argument_definition: b,
+                argument: rhs_arg,
+                resolved_value: resolve_argument(rhs_arg),
+            }),
+            _ => None,
+        };
 
-                InstructionWithOperands {
-                    raw_instruction,
-                    instruction: instruction_word,
-                    a: ResolvedValue {
-                        argument_definition: a,
-                        argument: lhs_arg,
-                        resolved_value: lhs
-                    },
-                    b: Some(ResolvedValue {
-                        argument_definition: b,
-                        argument: rhs_arg,
-                        resolved_value: rhs
-                    }),
-                }
-            }
-        }
-        else {
-            // A simpler version of above, we just need to anticipate the first operand.
-            let (lhs_arg, lhs) = cpu.resolve_argument(a, raw_1st);
-            assert!(a.has_extra_words() && raw_1st.is_some() || !a.has_extra_words());
-            assert!(raw_2nd.is_none());
-
-            InstructionWithOperands {
-                raw_instruction,
-                instruction: instruction_word,
-                a: ResolvedValue {
-                    argument_definition: a,
-                    argument: lhs_arg,
-                    resolved_value: lhs
-                },
-                b: None
-            }
+        InstructionWithOperands {
+            raw_instruction,
+            instruction: instruction_word,
+            a: ResolvedValue {
+                argument_definition: a,
+                argument: lhs_arg,
+                resolved_value: lhs,
+            },
+            b,
         }
     }
 
@@ -154,52 +158,57 @@ impl InstructionWithOperands {
 
 impl Debug for InstructionWithOperands {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        assert!(self.length_in_words() >= 1 && self.length_in_words() <= 3);
-
-        if self.length_in_words() == 1 {
-            write!(
-                f,
-                "{:04x?} ; {} ({:?})",
-                self.raw_instruction,
-                self.disassemble(),
-                self.disassemble_human()
-            )
-        } else if self.length_in_words() == 2 {
-            // The first value may be "inline", e.g. a default literal, a register etc.
-            // In this case we need to look up the second operand, which then must be a literal value.
-            let next_word = if self.a.argument_definition.num_extra_words() == 1 {
-                self.a.argument.get_literal().unwrap()
-            } else {
-                self.b
-                    .expect("require second argument")
-                    .argument
-                    .get_literal()
-                    .unwrap()
-            };
-            write!(
-                f,
-                "{:04x?} {:04x?} ; {} ({:?})",
-                self.raw_instruction,
-                next_word,
-                self.disassemble(),
-                self.disassemble_human()
-            )
-        } else {
-            assert_eq!(self.a.argument_definition.num_extra_words(), 1);
-            assert_eq!(self.b.expect("require second argument").argument_definition.num_extra_words(), 1);
-            write!(
-                f,
-                "{:04x?} {:04x?} {:04x?} ; {} ({:?})",
-                self.raw_instruction,
-                self.a.argument.get_literal().unwrap(),
-                self.b
-                    .expect("require second argument")
-                    .argument
-                    .get_literal()
-                    .unwrap(),
-                self.disassemble(),
-                self.disassemble_human()
-            )
+        let mut words = Vec::with_capacity(self.length_in_words());
+        if self.instruction.num_prefix_words() == 1 {
+            words.push(EXTENDED_PREFIX);
+        }
+        words.push(self.raw_instruction);
+
+        // Operands may be "inline", e.g. short literals or registers, and then take no extra word.
+        for value in std::iter::once(self.a).chain(self.b) {
+            if value.argument_definition.num_extra_words() == 1 {
+                words.extend(value.argument.get_literal());
+            }
         }
+        debug_assert_eq!(words.len(), self.length_in_words());
+
+        let words: Vec<String> = words.iter().map(|word| format!("{:04x?}", word)).collect();
+        write!(
+            f,
+            "{} ; {} ({:?})",
+            words.join(" "),
+            self.disassemble(),
+            self.disassemble_human()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_extended_instruction_works() {
+        // MLI A, B
+        let instruction = Instruction::OneWord {
+            instruction: InstructionWord::decode_extended(0x0404),
+            raw_instruction: 0x0404,
+        };
+        let formatted = format!("{:?}", InstructionWithOperands::resolve_static(instruction));
+        assert!(formatted.starts_with("03e0 0404 ; MLI A, B ("));
+
+        // DVI 0x1000, 0x20
+        let instruction = Instruction::ThreeWord {
+            instruction: InstructionWord::decode_extended(0x7df5),
+            raw_instruction: 0x7df5,
+            raw_1st: 0x1000,
+            raw_2nd: 0x0020,
+        };
+        let formatted = format!("{:?}", InstructionWithOperands::resolve_static(instruction));
+        assert!(
+            formatted.starts_with("03e0 7df5 1000 0020 ; DVI 0x1000, 0x20 ("),
+            "{}",
+            formatted
+        );
     }
 }
@@ -1,4 +1,5 @@
-use crate::{Decode, Register, Word};
+use crate::{Decode, DurationCycles, Register, Word};
+use std::fmt::{Display, Formatter};
 
 /// The argument of an instruction.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -158,6 +159,14 @@ impl InstructionArgumentDefinition {
     }
 }
 
+impl DurationCycles for InstructionArgumentDefinition {
+    /// Values that read a word (`0x10-0x17`, `0x1e` and `0x1f`) take 1 cycle to look up.
+    /// The rest take 0 cycles.
+    fn base_cycle_count(&self) -> usize {
+        self.num_extra_words()
+    }
+}
+
 impl Decode for InstructionArgumentDefinition {
     fn decode(value: Word) -> Self {
         assert!(value < 0x40);
@@ -187,6 +196,28 @@ impl Decode for InstructionArgumentDefinition {
     }
 }
 
+impl Display for InstructionArgumentDefinition {
+    /// Displays the addressing mode, using `next` as a placeholder for the next word.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Register { register } => write!(f, "{}", register),
+            Self::AtAddressFromRegister { register } => write!(f, "[{}]", register),
+            Self::AtAddressFromNextWordPlusRegister { register } => {
+                write!(f, "[next+{}]", register)
+            }
+            Self::Pop => f.write_str("POP"),
+            Self::Peek => f.write_str("PEEK"),
+            Self::Push => f.write_str("PUSH"),
+            Self::OfStackPointer => f.write_str("SP"),
+            Self::OfProgramCounter => f.write_str("PC"),
+            Self::OfOverflow => f.write_str("O"),
+            Self::AtAddressFromNextWord => f.write_str("[next]"),
+            Self::NextWordLiteral => f.write_str("next"),
+            Self::Literal { value } => write!(f, "0x{:02X}", value),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -312,4 +343,28 @@ mod tests {
             InstructionArgumentDefinition::Literal { value: 0x1f }
         );
     }
+
+    #[test]
+    fn base_cycle_count_works() {
+        assert_eq!(
+            InstructionArgumentDefinition::AtAddressFromNextWord.base_cycle_count(),
+            1
+        );
+        assert_eq!(
+            InstructionArgumentDefinition::AtAddressFromNextWordPlusRegister {
+                register: Register::I
+            }
+            .base_cycle_count(),
+            1
+        );
+        assert_eq!(
+            InstructionArgumentDefinition::NextWordLiteral.base_cycle_count(),
+            1
+        );
+        assert_eq!(InstructionArgumentDefinition::Pop.base_cycle_count(), 0);
+        assert_eq!(
+            InstructionArgumentDefinition::Literal { value: 0x1f }.base_cycle_count(),
+            0
+        );
+    }
 }
@@ -1,7 +1,10 @@
 use crate::instruction_argument::InstructionArgumentDefinition;
-use std::fmt::Debug;
-use tracing::trace;
-use crate::{Decode, Word};
+use std::fmt::{Debug, Display, Formatter};
+use crate::log::trace;
+use crate::{Decode, DurationCycles, Word};
+
+/// The prefix word of an extended instruction, i.e. the non-basic opcode `0x3e` with `a` set to zero.
+pub const EXTENDED_PREFIX: Word = 0x3e << 4;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum InstructionWord {
@@ -67,6 +70,42 @@ pub enum InstructionWord {
     ///
     /// Takes 2 cycles, plus the cost of `a` and `b`, plus 1 if the test fails.
     Ifb { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
+    /// Like `MUL`, but treats `a` and `b` as signed. Sets `O` to `((a*b)>>16)&0xffff`.
+    ///
+    /// This is an extended instruction, see [`InstructionWord::decode_extended`].
+    /// Takes 2 cycles, plus the cost of `a` and `b`.
+    Mli { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
+    /// Like `DIV`, but treats `a` and `b` as signed and rounds towards zero.
+    /// Sets `O` to `((a<<16)/b)&0xffff`. if `b==0`, sets `a` and `O` to `0` instead.
+    ///
+    /// This is an extended instruction, see [`InstructionWord::decode_extended`].
+    /// Takes 3 cycles, plus the cost of `a` and `b`.
+    Dvi { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
+    /// Sets `a` to `b`, then increases `I` and `J` by one.
+    ///
+    /// This is an extended instruction, see [`InstructionWord::decode_extended`].
+    /// Takes 2 cycles, plus the cost of `a` and `b`.
+    Sti { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
+    /// Sets `a` to `b`, then decreases `I` and `J` by one.
+    ///
+    /// This is an extended instruction, see [`InstructionWord::decode_extended`].
+    /// Takes 2 cycles, plus the cost of `a` and `b`.
+    Std { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
+    /// Performs next instruction only if `a>b`, treating `a` and `b` as signed.
+    ///
+    /// This is an extended instruction, see [`InstructionWord::decode_extended`].
+    /// Takes 2 cycles, plus the cost of `a` and `b`, plus 1 if the test fails.
+    Ifa { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
+    /// Performs next instruction only if `a<b`.
+    ///
+    /// This is an extended instruction, see [`InstructionWord::decode_extended`].
+    /// Takes 2 cycles, plus the cost of `a` and `b`, plus 1 if the test fails.
+    Ifl { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
+    /// Performs next instruction only if `a<b`, treating `a` and `b` as signed.
+    ///
+    /// This is an extended instruction, see [`InstructionWord::decode_extended`].
+    /// Takes 2 cycles, plus the cost of `a` and `b`, plus 1 if the test fails.
+    Ifu { a: InstructionArgumentDefinition, b: InstructionArgumentDefinition },
 }
 
 /// Non-basic opcodes always have their lower four bits unset, have one value and a six bit opcode.
@@ -79,6 +118,40 @@ pub enum NonBasicInstruction {
     /// Pushes the address of the next instruction to the stack, then sets `PC` to `a`.
     /// Takes 2 cycles, plus the cost of `a`.
     Jsr { a: InstructionArgumentDefinition },
+    /// Triggers a software interrupt with message `a`.
+    /// Takes 4 cycles, plus the cost of `a`.
+    Int { a: InstructionArgumentDefinition },
+    /// Sets `a` to `IA`.
+    /// Takes 1 cycle, plus the cost of `a`.
+    Iag { a: InstructionArgumentDefinition },
+    /// Sets `IA` to `a`.
+    /// Takes 1 cycle, plus the cost of `a`.
+    Ias { a: InstructionArgumentDefinition },
+    /// Disables interrupt queueing, pops `A` from the stack, then pops `PC` from the stack.
+    /// Takes 3 cycles, plus the cost of `a`.
+    Rfi { a: InstructionArgumentDefinition },
+    /// If `a` is nonzero, interrupts will be added to the queue instead of triggered.
+    /// If `a` is zero, interrupts will be triggered as normal again.
+    /// Takes 2 cycles, plus the cost of `a`.
+    Iaq { a: InstructionArgumentDefinition },
+    /// Sets `a` to the number of connected hardware devices.
+    /// Takes 2 cycles, plus the cost of `a`.
+    Hwn { a: InstructionArgumentDefinition },
+    /// Sets `A`, `B`, `C`, `X`, `Y` registers to information about hardware `a`.
+    /// `A+(B<<16)` is a 32 bit word identifying the hardware id,
+    /// `C` is the hardware version,
+    /// `X+(Y<<16)` is a 32 bit word identifying the manufacturer.
+    /// Takes 4 cycles, plus the cost of `a`.
+    Hwq { a: InstructionArgumentDefinition },
+    /// Sends an interrupt to hardware `a`.
+    /// Takes 4 cycles, plus the cost of `a`.
+    Hwi { a: InstructionArgumentDefinition },
+    /// Halts the CPU; this is an extension to the specification. The value `a` is ignored.
+    /// Takes 1 cycle, plus the cost of `a`.
+    Hlt { a: InstructionArgumentDefinition },
+    /// Announces that the next word is an extended instruction.
+    /// See [`InstructionWord::decode_extended`].
+    Extended,
 }
 
 impl Decode for InstructionWord {
@@ -128,13 +201,137 @@ impl Decode for NonBasicInstruction {
         match opcode {
             0x00 => NonBasicInstruction::Reserved,
             0x01 => NonBasicInstruction::Jsr { a },
-            0x02..=0x3f => NonBasicInstruction::Reserved,
+            0x02..=0x07 => NonBasicInstruction::Reserved,
+            0x08 => NonBasicInstruction::Int { a },
+            0x09 => NonBasicInstruction::Iag { a },
+            0x0a => NonBasicInstruction::Ias { a },
+            0x0b => NonBasicInstruction::Rfi { a },
+            0x0c => NonBasicInstruction::Iaq { a },
+            0x0d..=0x0f => NonBasicInstruction::Reserved,
+            0x10 => NonBasicInstruction::Hwn { a },
+            0x11 => NonBasicInstruction::Hwq { a },
+            0x12 => NonBasicInstruction::Hwi { a },
+            0x13..=0x3c => NonBasicInstruction::Reserved,
+            0x3d => NonBasicInstruction::Hlt { a },
+            // Only the prefix word itself introduces an extended instruction.
+            0x3e if a_word == 0 => NonBasicInstruction::Extended,
+            0x3e => NonBasicInstruction::Reserved,
+            0x3f => NonBasicInstruction::Reserved,
             _ => panic!(),
         }
     }
 }
 
+impl DurationCycles for InstructionWord {
+    fn base_cycle_count(&self) -> usize {
+        match self {
+            Self::NonBasic(op) => op.base_cycle_count(),
+            Self::Set { a, b } => 1 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::And { a, b } => 1 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Bor { a, b } => 1 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Xor { a, b } => 1 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Add { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Sub { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Mul { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Shr { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Shl { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Div { a, b } => 3 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Mod { a, b } => 3 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Ife { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Ifn { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Ifg { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Ifb { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Mli { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Dvi { a, b } => 3 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Sti { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Std { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Ifa { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Ifl { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+            Self::Ifu { a, b } => 2 + a.base_cycle_count() + b.base_cycle_count(),
+        }
+    }
+}
+
+impl DurationCycles for NonBasicInstruction {
+    fn base_cycle_count(&self) -> usize {
+        match self {
+            Self::Reserved => 0,
+            Self::Jsr { a } => 2 + a.base_cycle_count(),
+            Self::Int { a } => 4 + a.base_cycle_count(),
+            Self::Iag { a } => 1 + a.base_cycle_count(),
+            Self::Ias { a } => 1 + a.base_cycle_count(),
+            Self::Rfi { a } => 3 + a.base_cycle_count(),
+            Self::Iaq { a } => 2 + a.base_cycle_count(),
+            Self::Hwn { a } => 2 + a.base_cycle_count(),
+            Self::Hwq { a } => 4 + a.base_cycle_count(),
+            Self::Hwi { a } => 4 + a.base_cycle_count(),
+            Self::Hlt { a } => 1 + a.base_cycle_count(),
+            Self::Extended => 0,
+        }
+    }
+}
+
 impl InstructionWord {
+    /// Decodes an extended instruction from the word following the [`EXTENDED_PREFIX`].
+    ///
+    /// The basic opcode table is fully used, hence additional two-operand instructions are
+    /// encoded as two words: the prefix (non-basic opcode `0x3e`), followed by a word in the
+    /// basic instruction format `bbbbbbaaaaaaoooo`. Its opcode selects the instruction:
+    ///
+    /// - `0x1`: `STI`
+    /// - `0x2`: `STD`
+    /// - `0x4`: `MLI`
+    /// - `0x5`: `DVI`
+    /// - `0xc`: `IFA`
+    /// - `0xd`: `IFL`
+    /// - `0xe`: `IFU`
+    ///
+    /// All other opcodes are reserved.
+    pub fn decode_extended(value: Word) -> Self {
+        let opcode = value & 0b1111;
+        let a = InstructionArgumentDefinition::decode((value >> 4) & 0b111_111);
+        let b = InstructionArgumentDefinition::decode((value >> 10) & 0b111_111);
+
+        match opcode {
+            0x1 => Self::Sti { a, b },
+            0x2 => Self::Std { a, b },
+            0x4 => Self::Mli { a, b },
+            0x5 => Self::Dvi { a, b },
+            0xc => Self::Ifa { a, b },
+            0xd => Self::Ifl { a, b },
+            0xe => Self::Ifu { a, b },
+            _ => Self::NonBasic(NonBasicInstruction::Reserved),
+        }
+    }
+
+    /// Indicates whether the instruction is a test, i.e. one of the `IFx` instructions.
+    pub fn is_test(&self) -> bool {
+        matches!(
+            self,
+            Self::Ife { .. }
+                | Self::Ifn { .. }
+                | Self::Ifg { .. }
+                | Self::Ifb { .. }
+                | Self::Ifa { .. }
+                | Self::Ifl { .. }
+                | Self::Ifu { .. }
+        )
+    }
+
+    /// Gets the number of prefix words preceding the instruction word.
+    pub fn num_prefix_words(&self) -> usize {
+        match self {
+            Self::Mli { .. }
+            | Self::Dvi { .. }
+            | Self::Sti { .. }
+            | Self::Std { .. }
+            | Self::Ifa { .. }
+            | Self::Ifl { .. }
+            | Self::Ifu { .. } => 1,
+            _ => 0,
+        }
+    }
+
     /// Gets the length of the instruction in words.
     pub fn length_in_words(&self) -> usize {
         let len_from_values = match self {
@@ -154,10 +351,17 @@ impl InstructionWord {
             Self::Ifn { a, b } => a.num_extra_words() + b.num_extra_words(),
             Self::Ifg { a, b } => a.num_extra_words() + b.num_extra_words(),
             Self::Ifb { a, b } => a.num_extra_words() + b.num_extra_words(),
+            Self::Mli { a, b } => a.num_extra_words() + b.num_extra_words(),
+            Self::Dvi { a, b } => a.num_extra_words() + b.num_extra_words(),
+            Self::Sti { a, b } => a.num_extra_words() + b.num_extra_words(),
+            Self::Std { a, b } => a.num_extra_words() + b.num_extra_words(),
+            Self::Ifa { a, b } => a.num_extra_words() + b.num_extra_words(),
+            Self::Ifl { a, b } => a.num_extra_words() + b.num_extra_words(),
+            Self::Ifu { a, b } => a.num_extra_words() + b.num_extra_words(),
         };
 
         // We're adding one to count this instruction in.
-        1 + len_from_values
+        1 + self.num_prefix_words() + len_from_values
     }
 
     /// Unpacks the instruction arguments into a first value and an optional second value.
@@ -179,6 +383,13 @@ impl InstructionWord {
             Self::Ifn { a, b } => (*a, Some(*b)),
             Self::Ifg { a, b } => (*a, Some(*b)),
             Self::Ifb { a, b } => (*a, Some(*b)),
+            Self::Mli { a, b } => (*a, Some(*b)),
+            Self::Dvi { a, b } => (*a, Some(*b)),
+            Self::Sti { a, b } => (*a, Some(*b)),
+            Self::Std { a, b } => (*a, Some(*b)),
+            Self::Ifa { a, b } => (*a, Some(*b)),
+            Self::Ifl { a, b } => (*a, Some(*b)),
+            Self::Ifu { a, b } => (*a, Some(*b)),
         }
     }
 }
@@ -191,19 +402,102 @@ impl NonBasicInstruction {
         match self {
             Self::Reserved => 0,
             Self::Jsr { a } => a.num_extra_words(),
+            Self::Int { a } => a.num_extra_words(),
+            Self::Iag { a } => a.num_extra_words(),
+            Self::Ias { a } => a.num_extra_words(),
+            Self::Rfi { a } => a.num_extra_words(),
+            Self::Iaq { a } => a.num_extra_words(),
+            Self::Hwn { a } => a.num_extra_words(),
+            Self::Hwq { a } => a.num_extra_words(),
+            Self::Hwi { a } => a.num_extra_words(),
+            Self::Hlt { a } => a.num_extra_words(),
+            Self::Extended => 0,
         }
     }
 
     /// Unpacks the instruction arguments into a first value and an optional second value.
+    ///
+    /// Reserved instructions and a lone extended prefix have no arguments; a literal zero
+    /// is used as a placeholder so that they can be read and skipped like any other
+    /// instruction.
     pub fn unpack(&self) -> (InstructionArgumentDefinition, Option<InstructionArgumentDefinition>) {
         match self {
-            Self::Reserved => panic!(),
+            Self::Reserved => (InstructionArgumentDefinition::Literal { value: 0 }, None),
             Self::Jsr { a } => (*a, None),
+            Self::Int { a } => (*a, None),
+            Self::Iag { a } => (*a, None),
+            Self::Ias { a } => (*a, None),
+            Self::Rfi { a } => (*a, None),
+            Self::Iaq { a } => (*a, None),
+            Self::Hwn { a } => (*a, None),
+            Self::Hwq { a } => (*a, None),
+            Self::Hwi { a } => (*a, None),
+            Self::Hlt { a } => (*a, None),
+            Self::Extended => (InstructionArgumentDefinition::Literal { value: 0 }, None),
+        }
+    }
+}
+
+impl InstructionWord {
+    /// Gets the mnemonic of the instruction, e.g. `SET`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::NonBasic(NonBasicInstruction::Reserved) => "RESERVED",
+            Self::NonBasic(NonBasicInstruction::Extended) => "EXTENDED",
+            Self::NonBasic(NonBasicInstruction::Jsr { .. }) => "JSR",
+            Self::NonBasic(NonBasicInstruction::Int { .. }) => "INT",
+            Self::NonBasic(NonBasicInstruction::Iag { .. }) => "IAG",
+            Self::NonBasic(NonBasicInstruction::Ias { .. }) => "IAS",
+            Self::NonBasic(NonBasicInstruction::Rfi { .. }) => "RFI",
+            Self::NonBasic(NonBasicInstruction::Iaq { .. }) => "IAQ",
+            Self::NonBasic(NonBasicInstruction::Hwn { .. }) => "HWN",
+            Self::NonBasic(NonBasicInstruction::Hwq { .. }) => "HWQ",
+            Self::NonBasic(NonBasicInstruction::Hwi { .. }) => "HWI",
+            Self::NonBasic(NonBasicInstruction::Hlt { .. }) => "HLT",
+            Self::Set { .. } => "SET",
+            Self::Add { .. } => "ADD",
+            Self::Sub { .. } => "SUB",
+            Self::Mul { .. } => "MUL",
+            Self::Div { .. } => "DIV",
+            Self::Mod { .. } => "MOD",
+            Self::Shl { .. } => "SHL",
+            Self::Shr { .. } => "SHR",
+            Self::And { .. } => "AND",
+            Self::Bor { .. } => "BOR",
+            Self::Xor { .. } => "XOR",
+            Self::Ife { .. } => "IFE",
+            Self::Ifn { .. } => "IFN",
+            Self::Ifg { .. } => "IFG",
+            Self::Ifb { .. } => "IFB",
+            Self::Mli { .. } => "MLI",
+            Self::Dvi { .. } => "DVI",
+            Self::Sti { .. } => "STI",
+            Self::Std { .. } => "STD",
+            Self::Ifa { .. } => "IFA",
+            Self::Ifl { .. } => "IFL",
+            Self::Ifu { .. } => "IFU",
+        }
+    }
+}
+
+impl Display for InstructionWord {
+    /// Displays the mnemonic and the addressing modes of the values, e.g. `SET [next+I], [A]`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.mnemonic();
+        if let Self::NonBasic(NonBasicInstruction::Reserved | NonBasicInstruction::Extended) = self
+        {
+            return f.write_str(mnemonic);
+        }
+
+        match self.unpack() {
+            (a, Some(b)) => write!(f, "{} {}, {}", mnemonic, a, b),
+            (a, None) => write!(f, "{} {}", mnemonic, a),
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use super::*;
     use crate::register::Register;
@@ -238,6 +532,132 @@ mod tests {
         );
     }
 
+    #[test]
+    fn non_basic_instruction_interrupts_work() {
+        let a = InstructionArgumentDefinition::Register {
+            register: Register::A,
+        };
+        assert_eq!(
+            InstructionWord::decode(0b000000_001000_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Int { a })
+        );
+        assert_eq!(
+            InstructionWord::decode(0b000000_001001_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Iag { a })
+        );
+        assert_eq!(
+            InstructionWord::decode(0b000000_001010_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Ias { a })
+        );
+        assert_eq!(
+            InstructionWord::decode(0b000000_001011_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Rfi { a })
+        );
+        assert_eq!(
+            InstructionWord::decode(0b000000_001100_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Iaq { a })
+        );
+    }
+
+    #[test]
+    fn non_basic_instruction_hardware_works() {
+        let a = InstructionArgumentDefinition::Register {
+            register: Register::A,
+        };
+        assert_eq!(
+            InstructionWord::decode(0b000000_010000_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Hwn { a })
+        );
+        assert_eq!(
+            InstructionWord::decode(0b000000_010001_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Hwq { a })
+        );
+        assert_eq!(
+            InstructionWord::decode(0b000000_010010_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Hwi { a })
+        );
+    }
+
+    #[test]
+    fn non_basic_instruction_hlt_works() {
+        assert_eq!(
+            InstructionWord::decode(0b100000_111101_0000),
+            InstructionWord::NonBasic(NonBasicInstruction::Hlt {
+                a: InstructionArgumentDefinition::Literal { value: 0 }
+            })
+        );
+    }
+
+    #[test]
+    fn display_works() {
+        // SET [0x2000+I], [A]
+        assert_eq!(
+            InstructionWord::decode(0x2161).to_string(),
+            "SET [next+I], [A]"
+        );
+        // JSR 0x18
+        assert_eq!(InstructionWord::decode(0x7c10).to_string(), "JSR next");
+        assert_eq!(InstructionWord::decode(0x0000).to_string(), "RESERVED");
+    }
+
+    #[test]
+    fn extended_instruction_works() {
+        let a = InstructionArgumentDefinition::Register {
+            register: Register::A,
+        };
+        let b = InstructionArgumentDefinition::Literal { value: 0x02 };
+        assert_eq!(
+            InstructionWord::decode(EXTENDED_PREFIX),
+            InstructionWord::NonBasic(NonBasicInstruction::Extended)
+        );
+        assert_eq!(
+            InstructionWord::decode(EXTENDED_PREFIX | 0x0400),
+            InstructionWord::NonBasic(NonBasicInstruction::Reserved)
+        );
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_0100),
+            InstructionWord::Mli { a, b }
+        );
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_0101),
+            InstructionWord::Dvi { a, b }
+        );
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_0001),
+            InstructionWord::Sti { a, b }
+        );
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_0010),
+            InstructionWord::Std { a, b }
+        );
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_1100),
+            InstructionWord::Ifa { a, b }
+        );
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_1101),
+            InstructionWord::Ifl { a, b }
+        );
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_1110),
+            InstructionWord::Ifu { a, b }
+        );
+        assert!(InstructionWord::Ifu { a, b }.is_test());
+        assert_eq!(
+            InstructionWord::decode_extended(0b100010_000000_0011),
+            InstructionWord::NonBasic(NonBasicInstruction::Reserved)
+        );
+        assert_eq!(InstructionWord::Dvi { a, b }.length_in_words(), 2);
+    }
+
+    #[test]
+    fn lone_extended_prefix_unpacks_to_placeholder() {
+        assert_eq!(
+            InstructionWord::decode(EXTENDED_PREFIX).unpack(),
+            (InstructionArgumentDefinition::Literal { value: 0 }, None)
+        );
+    }
+
     #[test]
     fn set_works() {
         assert_eq!(
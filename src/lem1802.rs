@@ -0,0 +1,115 @@
+use crate::log::debug;
+use crate::{Device, Register, Word, DCPU16};
+
+/// The number of character columns of the screen.
+pub const SCREEN_COLUMNS: usize = 32;
+
+/// The number of character rows of the screen.
+pub const SCREEN_ROWS: usize = 12;
+
+/// The LEM1802 monitor (`0x7349f615`), reduced to its text content.
+///
+/// The monitor responds to `HWI` with `A=0` (`MEM_MAP_SCREEN`), mapping the video RAM
+/// to the address in `B`; a `B` of zero disconnects the screen. Fonts, palettes
+/// and the border color are not supported.
+#[derive(Debug, Default, Clone)]
+pub struct Lem1802 {
+    /// The start of the video RAM; zero if the screen is disconnected.
+    video_ram: Word,
+}
+
+impl Lem1802 {
+    /// Creates a disconnected monitor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the start of the video RAM, or `None` if the screen is disconnected.
+    pub fn video_ram(&self) -> Option<Word> {
+        if self.video_ram == 0 {
+            None
+        } else {
+            Some(self.video_ram)
+        }
+    }
+
+    /// Renders the character cells as newline-separated rows, ignoring colors and blinking.
+    ///
+    /// The low seven bits of each cell are interpreted as ASCII; non-printable characters
+    /// are rendered as spaces. Returns an empty string if the screen is disconnected.
+    pub fn text(&self, cpu: &DCPU16) -> String {
+        let start = match self.video_ram() {
+            Some(start) => start,
+            None => return String::new(),
+        };
+
+        let rows: Vec<String> = (0..SCREEN_ROWS)
+            .map(|row| {
+                (0..SCREEN_COLUMNS)
+                    .map(|column| {
+                        let offset = (row * SCREEN_COLUMNS + column) as Word;
+                        let character = (cpu.read_memory(start.wrapping_add(offset)) & 0x7f) as u8;
+                        if character.is_ascii_graphic() {
+                            character as char
+                        } else {
+                            ' '
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        rows.join("\n")
+    }
+}
+
+impl Device for Lem1802 {
+    fn id(&self) -> u32 {
+        0x7349f615
+    }
+
+    fn version(&self) -> u16 {
+        0x1802
+    }
+
+    fn manufacturer(&self) -> u32 {
+        0x1c6c8b36
+    }
+
+    fn interrupt(&mut self, cpu: &mut DCPU16) {
+        if cpu.register(Register::A) == 0 {
+            self.video_ram = cpu.register(Register::B);
+            debug!("Screen mapped to {address:04X}", address = self.video_ram);
+        }
+    }
+
+    fn tick(&mut self, _cpu: &mut DCPU16) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_works() {
+        let mut screen = Lem1802::new();
+        let mut cpu = DCPU16::new(&[]);
+        assert_eq!(screen.text(&cpu), "");
+
+        cpu.set_register(Register::B, 0x8000);
+        screen.interrupt(&mut cpu);
+        assert_eq!(screen.video_ram(), Some(0x8000));
+
+        // Colors are ignored.
+        for (offset, character) in "Hi!".bytes().enumerate() {
+            cpu.set_ram(0x8000 + offset as Word, 0xf000 | character as Word);
+        }
+        cpu.set_ram(0x8000 + SCREEN_COLUMNS as Word + 1, 'x' as Word);
+
+        let text = screen.text(&cpu);
+        let rows: Vec<&str> = text.split('\n').collect();
+        assert_eq!(rows.len(), SCREEN_ROWS);
+        assert_eq!(rows[0], format!("{:<32}", "Hi!"));
+        assert_eq!(rows[1], format!("{:<32}", " x"));
+        assert!(rows[2..].iter().all(|row| row.trim().is_empty()));
+    }
+}
@@ -1,40 +1,94 @@
 #[cfg(feature = "assembler")]
 mod assembler;
+pub mod binary;
+mod builder;
+mod clock;
+mod debug;
+mod decode;
+mod device;
+mod diff;
 mod disassemble;
+pub mod disassembler;
+#[cfg(test)]
+pub(crate) mod fixtures;
 mod instruction;
 mod instruction_argument;
 mod instruction_word;
+mod lem1802;
+mod log;
+mod memory;
+mod outcome;
+mod profile;
 mod register;
+mod snapshot;
+mod spec;
+mod state;
+mod trace;
+mod word;
 
 #[cfg(feature = "assembler")]
-pub use crate::assembler::assemble;
+pub use crate::assembler::{
+    assemble, assemble_line, assemble_units, assemble_with_notes, assemble_with_resolver,
+    assemble_with_symbols, assembled_size, AssembleError, AssembleNote,
+};
+pub use crate::binary::LoadError;
+pub use crate::builder::Dcpu16Builder;
+pub use crate::clock::{Clock, DEFAULT_STEPS_PER_PERIOD};
+use crate::debug::WatchHit;
+pub use crate::decode::{decode_instruction, instruction_len_at, instructions, DecodedInstruction};
+pub use crate::device::Device;
+pub use crate::diff::StateDiff;
+use crate::disassembler::DisassemblyOptions;
 use crate::instruction::{Instruction, InstructionWithOperands};
-use crate::instruction_argument::{
+pub use crate::instruction_argument::{
     InstructionArgument, InstructionArgumentDefinition, SpecialRegister, StackOperation,
 };
-use crate::instruction_word::{InstructionWord, NonBasicInstruction};
-pub use crate::register::Register;
+use crate::instruction_word::EXTENDED_PREFIX;
+pub use crate::instruction_word::{InstructionWord, NonBasicInstruction};
+pub use crate::lem1802::{Lem1802, SCREEN_COLUMNS, SCREEN_ROWS};
+use crate::log::{debug, info, trace, warn};
+pub use crate::memory::Memory;
+use crate::memory::{MemoryMapping, Ram};
+pub use crate::outcome::{LiteralWritePolicy, RunOutcome, StepResult};
+pub use crate::profile::OpcodeProfile;
+pub use crate::register::{ParseRegisterError, Register};
+pub use crate::snapshot::CpuSnapshot;
+pub use crate::spec::SpecVersion;
+pub use crate::state::{CpuState, RamRun};
+pub use crate::trace::{TraceEvent, TraceHook, TraceRecord};
+pub use crate::word::CpuWord;
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
 use std::ops::{BitAnd, BitOr, BitXor};
-use tracing::{debug, info, trace, warn};
 
 type Word = u16;
 
 const NUM_REGISTERS: usize = 8;
-const NUM_RAM_WORDS: usize = 0x10000;
+/// The number of words of the full RAM.
+pub const NUM_RAM_WORDS: usize = 0x10000;
 
 // Stack pointer is initialized to 0xffff (for 0x10000 words of memory).
 const STACK_POINTER_INIT: usize = NUM_RAM_WORDS - 1;
 
+// The maximum number of interrupts that can be queued at a time.
+const MAX_QUEUED_INTERRUPTS: usize = 256;
+
 /// Decoding of instructions or values.
 trait Decode {
     /// Decodes the specified word.
     fn decode(value: Word) -> Self;
 }
 
+/// Cycle costs of instructions or values.
+trait DurationCycles {
+    /// Gets the number of cycles required, excluding any penalty for failed tests.
+    fn base_cycle_count(&self) -> usize;
+}
+
 /// A DCPU-16 emulator.
 pub struct DCPU16<'p> {
     /// RAM.
-    ram: Box<[Word; NUM_RAM_WORDS]>,
+    ram: Ram<'p>,
     /// Registers.
     registers: [Word; NUM_REGISTERS],
     /// Program counter.
@@ -43,156 +97,653 @@ pub struct DCPU16<'p> {
     pub stack_pointer: Word,
     /// Overflow.
     pub overflow: Word,
+    /// Interrupt address.
+    ///
+    /// If zero, interrupts are ignored.
+    pub interrupt_address: Word,
 
     /// Program counter location of the last step.
     ///
     /// This value is used to determine a "crash loop" (a jump to the same instruction).
     previous_program_counter: Word,
-    /// The program
-    program: &'p [u16],
+    /// The program, either borrowed or e.g. decoded from bytes.
+    program: Cow<'p, [u16]>,
+    /// The number of words of the program loaded into RAM.
+    ///
+    /// This value is used to determine whether the end of the program was reached.
+    program_len: usize,
     /// Indicates whether the next instruction should be skipped.
     skip_next_intruction: bool,
+    /// The number of cycles consumed so far.
+    cycles: u64,
+    /// The number of instructions executed so far.
+    executed_count: u64,
+    /// The number of instructions skipped due to failed tests so far.
+    skipped_count: u64,
+    /// Interrupt messages waiting to be handled.
+    interrupt_queue: VecDeque<Word>,
+    /// Indicates whether interrupts are queued instead of triggered.
+    interrupt_queueing: bool,
+    /// Connected hardware devices.
+    devices: Vec<Box<dyn Device>>,
+    /// Memory backends handling address ranges instead of the RAM.
+    memory_maps: Vec<MemoryMapping>,
+    /// Indicates whether a jump to the same instruction is reported as a crash loop
+    /// rather than as an intentional halt.
+    halt_on_self_jump: bool,
+    /// The handling of assignments to a literal.
+    literal_write_policy: LiteralWritePolicy,
+    /// The specification revision the program is written against.
+    spec_version: SpecVersion,
+    /// Indicates whether the current instruction assigned to a literal.
+    literal_written: bool,
+    /// Indicates whether the CPU executed a `HLT` instruction.
+    halted: bool,
+    /// Indicates whether each step logs the executed instruction and the registers.
+    diagnostics: bool,
+    /// Indicates whether the current instruction wrote to a register, RAM or the stack pointer.
+    state_written: bool,
+    /// The highest RAM address written through an address operand.
+    high_water: Word,
+    /// A hook called after each step.
+    trace_hook: Option<TraceHook>,
+    /// The steps recorded since recording was enabled.
+    recording: Option<Vec<TraceRecord>>,
+    /// The options used to disassemble traced instructions.
+    trace_options: DisassemblyOptions,
+    /// Indicates whether executions are counted per opcode.
+    profiling_opcodes: bool,
+    /// The executions per opcode since profiling was enabled.
+    opcode_profile: OpcodeProfile,
+    /// Addresses at which [`DCPU16::run_until_break`] pauses execution.
+    breakpoints: HashSet<Word>,
+    /// RAM addresses at which [`DCPU16::run_until_break`] pauses execution when written.
+    write_watches: HashSet<Word>,
+    /// Registers at which [`DCPU16::run_until_break`] pauses execution when written.
+    register_watches: HashSet<Register>,
+    /// The first write to a watched address during the current step.
+    watch_hit: Option<WatchHit>,
 }
 
 impl<'p> DCPU16<'p> {
     pub fn new(program: &'p [u16]) -> Self {
+        Self::with_ram_words(program, NUM_RAM_WORDS)
+    }
+
+    /// Creates a CPU with a RAM of only `words` words, e.g. for isolated tests.
+    ///
+    /// The number of words must be a power of two of at most `0x10000`;
+    /// addresses beyond the RAM wrap around into the available words.
+    pub fn with_ram_words(program: &'p [u16], words: usize) -> Self {
+        Self::with_program(Cow::Borrowed(program), Ram::zeroed(words))
+    }
+
+    /// Creates a CPU using the caller-provided buffer as its RAM, e.g. to avoid
+    /// allocating the RAM on embedded targets.
+    ///
+    /// The buffer is cleared before the program is loaded into it.
+    pub fn with_ram(program: &'p [u16], ram: &'p mut [Word; NUM_RAM_WORDS]) -> Self {
+        ram.fill(0);
+        Self::with_program(Cow::Borrowed(program), Ram::Borrowed(ram))
+    }
+
+    /// Creates a CPU from a borrowed or owned program, using the zeroed RAM.
+    fn with_program(program: Cow<'p, [u16]>, ram: Ram<'p>) -> Self {
         assert!(program.len() < u16::MAX as usize);
-        let cpu = Self {
-            ram: Box::new([0; NUM_RAM_WORDS]),
+        assert!(
+            ram.len().is_power_of_two() && ram.len() <= NUM_RAM_WORDS,
+            "RAM size must be a power of two of at most {} words",
+            NUM_RAM_WORDS
+        );
+        assert!(program.len() <= ram.len(), "program exceeds the RAM");
+
+        let mut cpu = Self {
+            ram,
             registers: [0; NUM_REGISTERS],
             program_counter: 0,
             stack_pointer: STACK_POINTER_INIT as _,
             overflow: 0,
+            interrupt_address: 0,
+            program_len: program.len(),
             program,
             previous_program_counter: 0,
             skip_next_intruction: false,
+            cycles: 0,
+            executed_count: 0,
+            skipped_count: 0,
+            interrupt_queue: VecDeque::new(),
+            interrupt_queueing: false,
+            devices: Vec::new(),
+            memory_maps: Vec::new(),
+            halt_on_self_jump: true,
+            literal_write_policy: LiteralWritePolicy::Ignore,
+            spec_version: SpecVersion::V1_1,
+            literal_written: false,
+            halted: false,
+            diagnostics: true,
+            state_written: false,
+            high_water: 0,
+            trace_hook: None,
+            recording: None,
+            trace_options: DisassemblyOptions::default(),
+            profiling_opcodes: false,
+            opcode_profile: OpcodeProfile::default(),
+            breakpoints: HashSet::new(),
+            write_watches: HashSet::new(),
+            register_watches: HashSet::new(),
+            watch_hit: None,
         };
+        cpu.load_program_into_ram();
 
         info!(
             "Loaded {program_length} words of program data",
-            program_length = program.len()
+            program_length = cpu.program_len
         );
         cpu.dump_registers();
         cpu
     }
 
+    /// Restores the initial state of the CPU, keeping the loaded program
+    /// and the connected devices.
+    pub fn reset(&mut self) {
+        self.ram.fill(0);
+        self.load_program_into_ram();
+        self.registers = [0; NUM_REGISTERS];
+        self.program_counter = 0;
+        self.stack_pointer = STACK_POINTER_INIT as _;
+        self.overflow = 0;
+        self.interrupt_address = 0;
+        self.previous_program_counter = 0;
+        self.skip_next_intruction = false;
+        self.cycles = 0;
+        self.executed_count = 0;
+        self.skipped_count = 0;
+        self.interrupt_queue.clear();
+        self.interrupt_queueing = false;
+        self.halted = false;
+        self.high_water = 0;
+        self.opcode_profile = OpcodeProfile::default();
+
+        debug!("CPU reset");
+        self.dump_registers();
+    }
+
+    /// Replaces the program and copies it to the beginning of the RAM, e.g. to run
+    /// another program on the same machine.
+    ///
+    /// The program counter, the stack pointer and a pending skip are reset, so that
+    /// the new program starts from a clean state. The registers and the remaining RAM
+    /// are kept; use [`DCPU16::reset`] to clear them.
+    pub fn load_program(&mut self, program: &'p [u16]) {
+        assert!(program.len() <= self.ram.len(), "program exceeds the RAM");
+
+        self.program = Cow::Borrowed(program);
+        self.program_len = program.len();
+        self.load_program_into_ram();
+        self.program_counter = 0;
+        self.previous_program_counter = 0;
+        self.stack_pointer = STACK_POINTER_INIT as _;
+        self.skip_next_intruction = false;
+        self.halted = false;
+
+        info!(
+            "Loaded {program_length} words of program data",
+            program_length = self.program_len
+        );
+    }
+
+    /// Copies the program to the beginning of the RAM.
+    fn load_program_into_ram(&mut self) {
+        self.ram[..self.program_len].copy_from_slice(&self.program);
+    }
+
     /// Gets the value of the specified register.
     pub fn register(&self, register: Register) -> Word {
         self.registers[register as usize]
     }
 
+    /// Gets the value of the register with the specified name, e.g. `A` or `j`,
+    /// or `None` if there is no such register.
+    pub fn register_by_name(&self, name: &str) -> Option<Word> {
+        name.parse().ok().map(|register| self.register(register))
+    }
+
+    /// Sets the value of the specified register.
+    pub fn set_register(&mut self, register: Register, value: Word) {
+        self.registers[register as usize] = value;
+        self.state_written = true;
+    }
+
+    /// Gets the number of cycles consumed so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Gets the number of instructions executed so far.
+    pub fn executed_count(&self) -> u64 {
+        self.executed_count
+    }
+
+    /// Gets the number of instructions skipped due to failed tests so far.
+    pub fn skipped_count(&self) -> u64 {
+        self.skipped_count
+    }
+
+    /// Gets the overflow register, which later revisions of the specification call `EX`.
+    pub fn ex(&self) -> Word {
+        self.overflow
+    }
+
     /// Gets a reference to the RAM.
-    pub fn ram(&self) -> &[u16; NUM_RAM_WORDS] {
+    pub fn ram(&self) -> &[u16] {
         self.ram.as_ref()
     }
 
-    /// Gets a reference to the RAM.
-    pub fn ram_mut(&mut self) -> &[u16; NUM_RAM_WORDS] {
+    /// Gets `len` words of the RAM, starting at the specified address.
+    ///
+    /// Returns `None` if the range exceeds the RAM; unlike addresses used by instructions,
+    /// the range does not wrap around.
+    pub fn ram_slice(&self, start: Word, len: usize) -> Option<&[Word]> {
+        let start = start as usize;
+        self.ram.get(start..start.checked_add(len)?)
+    }
+
+    /// Gets a mutable reference to the RAM.
+    pub fn ram_mut(&mut self) -> &mut [u16] {
         self.ram.as_mut()
     }
 
+    /// Maps an address to an index into the RAM, wrapping around if the RAM is smaller
+    /// than the address space.
+    pub(crate) fn ram_index(&self, address: Word) -> usize {
+        address as usize & (self.ram.len() - 1)
+    }
+
+    /// Sets the value at the specified address in RAM.
+    ///
+    /// Note that this writes to the emulator's RAM, not to the program
+    /// the CPU was constructed with. Since instructions are fetched from RAM,
+    /// this can be used to patch the loaded program.
+    pub fn set_ram(&mut self, address: Word, value: Word) {
+        let index = self.ram_index(address);
+        self.ram[index] = value;
+    }
+
+    /// Triggers an interrupt with the specified message.
+    ///
+    /// The interrupt is handled before the next instruction is executed,
+    /// or once queueing is disabled again. If `IA` is zero, the interrupt is ignored.
+    pub fn trigger_interrupt(&mut self, message: Word) {
+        if self.interrupt_address == 0 {
+            trace!(
+                "Ignoring interrupt {message:04X} as IA is zero",
+                message = message
+            );
+            return;
+        }
+
+        if self.interrupt_queue.len() >= MAX_QUEUED_INTERRUPTS {
+            warn!(
+                "Interrupt queue overflow - dropping interrupt {message:04X}",
+                message = message
+            );
+            return;
+        }
+
+        self.interrupt_queue.push_back(message);
+    }
+
+    /// Configures how a jump to the same instruction that leaves all other state
+    /// unchanged, e.g. `:crash SET PC, crash`, is reported.
+    ///
+    /// If enabled (the default), execution stops with a [`StepResult::CrashLoop`] warning.
+    /// If disabled, the jump is considered an intentional halt and execution stops
+    /// with [`StepResult::Halted`] instead.
+    pub fn set_halt_on_self_jump(&mut self, enabled: bool) {
+        self.halt_on_self_jump = enabled;
+    }
+
+    /// Selects the specification revision the program is written against,
+    /// see [`SpecVersion`]. Defaults to [`SpecVersion::V1_1`].
+    pub fn set_spec_version(&mut self, version: SpecVersion) {
+        self.spec_version = version;
+    }
+
+    /// Gets the specification revision the program is written against.
+    pub fn spec_version(&self) -> SpecVersion {
+        self.spec_version
+    }
+
+    /// Configures how assignments to a literal, e.g. `SET 0x5, A`, are handled.
+    ///
+    /// Such assignments always fail silently. By default they are ignored; with
+    /// [`LiteralWritePolicy::Error`], execution stops with [`StepResult::LiteralWrite`]
+    /// after the instruction.
+    pub fn set_literal_write_policy(&mut self, policy: LiteralWritePolicy) {
+        self.literal_write_policy = policy;
+    }
+
+    /// Enables or disables the per-step logging of the executed instruction and the registers.
+    ///
+    /// Diagnostics are enabled by default. Disabling them avoids their overhead when executing
+    /// many steps, e.g. in batch runs; execution is otherwise identical.
+    pub fn set_diagnostics(&mut self, enabled: bool) {
+        self.diagnostics = enabled;
+    }
+
     /// Executes the program until a crash loop is detected.
+    ///
+    /// See [`run_to_completion()`](Self::run_to_completion) for a variant that reports why
+    /// the execution stopped.
     pub fn run(&mut self) {
-        while self.step() {}
+        self.run_to_completion();
+    }
+
+    /// Executes the program until a crash loop is detected, the program halts, the end of
+    /// the program is reached or a reserved instruction is encountered.
+    ///
+    /// Unlike [`run_with_limit()`](Self::run_with_limit), this never returns
+    /// [`RunOutcome::StepLimitReached`], hence it may not return for programs that loop.
+    pub fn run_to_completion(&mut self) -> RunOutcome {
+        loop {
+            if let Some(outcome) = self.step().outcome(self.previous_program_counter) {
+                return outcome;
+            }
+        }
+    }
+
+    /// Executes the program until a crash loop is detected, the program halts, the end of
+    /// the program is reached or `max_steps` instructions were executed, whichever comes first.
+    pub fn run_with_limit(&mut self, max_steps: u64) -> RunOutcome {
+        for _ in 0..max_steps {
+            if let Some(outcome) = self.step().outcome(self.previous_program_counter) {
+                return outcome;
+            }
+        }
+
+        RunOutcome::StepLimitReached
+    }
+
+    /// Executes whole instructions until the next one would exceed a budget of `budget` cycles,
+    /// or until the program stops for any of the reasons of [`run_with_limit()`](Self::run_with_limit).
+    ///
+    /// Since the outcome of a test is not known in advance, tests are budgeted
+    /// including the penalty cycle of a failed test.
+    pub fn run_cycles(&mut self, budget: u64) -> RunOutcome {
+        let start = self.cycles;
+        loop {
+            let consumed = self.cycles - start;
+            if consumed + self.next_instruction_cycles() > budget {
+                return RunOutcome::CycleLimitReached;
+            }
+
+            if let Some(outcome) = self.step().outcome(self.previous_program_counter) {
+                return outcome;
+            }
+        }
+    }
+
+    /// Gets the maximum number of cycles the next step consumes, without executing it.
+    fn next_instruction_cycles(&self) -> u64 {
+        // Skipped instructions don't consume cycles on their own.
+        if self.skip_next_intruction {
+            return 0;
+        }
+
+        // Mirrors the conditions of handle_interrupt().
+        let address = if !self.interrupt_queueing
+            && !self.interrupt_queue.is_empty()
+            && self.interrupt_address != 0
+        {
+            self.interrupt_address
+        } else {
+            self.program_counter
+        };
+
+        let instruction = self.decode_at(address).instruction;
+        let penalty = if instruction.is_test() { 1 } else { 0 };
+        instruction.base_cycle_count() as u64 + penalty
     }
 
     /// Executes a single instruction of the program.
-    pub fn step(&mut self) -> bool {
+    pub fn step(&mut self) -> StepResult {
+        if self.halted {
+            return StepResult::Halted;
+        }
+
+        self.tick_devices();
+        self.handle_interrupt();
+
         self.previous_program_counter = self.program_counter;
+        self.state_written = false;
+        self.literal_written = false;
+        self.watch_hit = None;
         let instruction = self.read_instruction();
+        let trace_event = self.begin_trace_event(&instruction);
 
-        if self.skip_next_intruction {
+        let result = if self.skip_next_intruction {
             self.execute_skipped_instruction(instruction);
+            StepResult::Skipped
         } else {
-            if !self.execute_instruction(instruction) {
-                return false;
-            }
+            self.execute_instruction(instruction)
+        };
+
+        self.end_trace_event(trace_event, result == StepResult::Skipped);
+        if result != StepResult::Executed && result != StepResult::Skipped {
+            return result;
         }
 
         // We print the state after the execution.
-        self.dump_registers();
+        if self.diagnostics {
+            self.dump_registers();
+        }
 
-        if (self.program_counter as usize) < self.program.len() {
-            return true;
+        if (self.program_counter as usize) < self.program_len {
+            return result;
         }
 
         warn!("End of program reached - terminating");
-        false
+        StepResult::EndOfProgram
     }
 
-    /// "Executes" a skipped instruction.
-    fn execute_skipped_instruction(&mut self, instruction: InstructionWithOperands) {
+    /// Handles at most one queued interrupt, unless interrupts are being queued.
+    fn handle_interrupt(&mut self) {
+        // A pending skip must apply to the instruction following the test.
+        if self.interrupt_queueing || self.skip_next_intruction {
+            return;
+        }
+
+        let message = match self.interrupt_queue.pop_front() {
+            Some(message) => message,
+            None => return,
+        };
+
+        // IA may have been cleared after the interrupt was queued.
+        if self.interrupt_address == 0 {
+            return;
+        }
+
         debug!(
-            "SKIP {operation_pc:04X}: {instruction:?}",
-            operation_pc = self.previous_program_counter,
-            instruction = instruction
+            "INT  {message:04X}: jumping to handler at {ia:04X}",
+            message = message,
+            ia = self.interrupt_address
         );
-        self.skip_next_intruction = false;
+
+        self.interrupt_queueing = true;
+        self.push(self.program_counter);
+        self.push(self.registers[Register::A as usize]);
+        self.program_counter = self.interrupt_address;
+        self.registers[Register::A as usize] = message;
+    }
+
+    /// Pushes a value onto the stack.
+    fn push(&mut self, value: Word) {
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+        let index = self.ram_index(self.stack_pointer);
+        self.ram[index] = value;
+        self.state_written = true;
+    }
+
+    /// Pops a value from the stack.
+    fn pop(&mut self) -> Word {
+        let value = self.ram[self.ram_index(self.stack_pointer)];
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        self.state_written = true;
+        value
+    }
+
+    /// "Executes" a skipped instruction.
+    fn execute_skipped_instruction(&mut self, instruction: InstructionWithOperands) {
+        if self.diagnostics {
+            debug!(
+                "SKIP {operation_pc:04X}: {instruction:?}",
+                operation_pc = self.previous_program_counter,
+                instruction = instruction
+            );
+        }
+        // Skipping a test also skips the instruction it guards, so that chained tests
+        // are skipped as a whole.
+        self.skip_next_intruction = instruction.instruction.is_test();
+        self.skipped_count += 1;
     }
 
     /// Executes an instruction.
-    fn execute_instruction(&mut self, instruction: InstructionWithOperands) -> bool {
-        debug!(
-            "EXEC {operation_pc:04X}: {instruction:?}",
-            operation_pc = self.previous_program_counter,
-            instruction = instruction
-        );
+    ///
+    /// Returns [`StepResult::Executed`], unless a jump to the same instruction or a reserved
+    /// instruction was detected.
+    fn execute_instruction(&mut self, instruction: InstructionWithOperands) -> StepResult {
+        // Reserved instructions are rejected before they are counted.
+        if let InstructionWord::NonBasic(
+            NonBasicInstruction::Reserved | NonBasicInstruction::Extended,
+        ) = instruction.instruction
+        {
+            return self.reject_instruction();
+        }
 
-        match instruction.instruction {
-            InstructionWord::NonBasic(nbi) => match nbi {
-                NonBasicInstruction::Reserved => panic!(),
+        if self.diagnostics {
+            debug!(
+                "EXEC {operation_pc:04X}: {instruction:?}",
+                operation_pc = self.previous_program_counter,
+                instruction = instruction
+            );
+        }
+
+        self.cycles += instruction.instruction.base_cycle_count() as u64;
+        self.executed_count += 1;
+        self.profile_opcode(&instruction.instruction);
+
+        match (instruction.instruction, instruction.b) {
+            (InstructionWord::NonBasic(nbi), _) => match nbi {
+                NonBasicInstruction::Reserved | NonBasicInstruction::Extended => {
+                    return self.reject_instruction();
+                }
                 NonBasicInstruction::Jsr { .. } => {
-                    assert!(instruction.b.is_none());
-                    self.stack_pointer -= 1;
-                    self.ram[self.stack_pointer as usize] = self.program_counter;
+                    self.push(self.program_counter);
                     self.program_counter = instruction.a.resolved_value;
                 }
+                NonBasicInstruction::Int { .. } => {
+                    self.trigger_interrupt(instruction.a.resolved_value);
+                }
+                NonBasicInstruction::Iag { .. } => {
+                    self.store_value(instruction.a.argument, self.interrupt_address);
+                }
+                NonBasicInstruction::Ias { .. } => {
+                    self.interrupt_address = instruction.a.resolved_value;
+                }
+                NonBasicInstruction::Rfi { .. } => {
+                    self.interrupt_queueing = false;
+                    self.registers[Register::A as usize] = self.pop();
+                    self.program_counter = self.pop();
+                }
+                NonBasicInstruction::Iaq { .. } => {
+                    self.interrupt_queueing = instruction.a.resolved_value != 0;
+                }
+                NonBasicInstruction::Hwn { .. } => {
+                    self.store_value(instruction.a.argument, self.num_devices());
+                }
+                NonBasicInstruction::Hwq { .. } => {
+                    self.query_device(instruction.a.resolved_value);
+                }
+                NonBasicInstruction::Hwi { .. } => {
+                    self.interrupt_device(instruction.a.resolved_value);
+                }
+                NonBasicInstruction::Hlt { .. } => {
+                    info!(
+                        "Program halted at PC={pc:04X}",
+                        pc = self.previous_program_counter
+                    );
+                    self.halted = true;
+                    return StepResult::Halted;
+                }
             },
-            InstructionWord::Set { .. } => {
-                self.store_value(
-                    instruction.a.argument,
-                    instruction
-                        .b
-                        .expect("require second argument")
-                        .resolved_value,
-                );
+            // Basic instructions always decode a second operand.
+            (_, None) => return self.reject_instruction(),
+            (InstructionWord::Set { .. }, Some(b)) => {
+                self.store_value(instruction.a.argument, b.resolved_value);
             }
-            InstructionWord::Add { .. } => {
+            (InstructionWord::Add { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
-                let (result, overflow) = lhs.overflowing_add(rhs);
-                self.overflow = if overflow { 0x0001 } else { 0x0 };
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.add_with_overflow(rhs);
+                self.overflow = overflow;
                 self.store_value(a, result);
             }
-            InstructionWord::Sub { .. } => {
+            (InstructionWord::Sub { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
-                let (result, overflow) = lhs.overflowing_sub(rhs);
-                self.overflow = if overflow { 0xffff } else { 0x0 };
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.sub_with_overflow(rhs);
+                self.overflow = overflow;
                 self.store_value(a, result);
             }
-            InstructionWord::Mul { .. } => {
+            (InstructionWord::Mul { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
-                let result = lhs.wrapping_mul(rhs);
-                self.overflow = (((lhs as u32 * rhs as u32) >> 16) & 0xffff) as _;
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.mul_with_overflow(rhs);
+                self.overflow = overflow;
                 self.store_value(a, result);
             }
-            InstructionWord::Div { .. } => {
+            (InstructionWord::Div { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
-                if rhs > 0 {
-                    let result = lhs.wrapping_div(rhs);
-                    self.overflow = ((((lhs as u32) << 16) / (rhs as u32)) & 0xffff) as _;
-                    self.store_value(a, result);
-                } else {
-                    self.overflow = 0;
-                    self.store_value(a, 0);
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.div_with_overflow(rhs);
+                self.overflow = overflow;
+                self.store_value(a, result);
+            }
+            (InstructionWord::Mli { .. }, Some(b)) => {
+                let (a, lhs) = instruction.a.unpack();
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.mli_with_overflow(rhs);
+                self.overflow = overflow;
+                self.store_value(a, result);
+            }
+            (InstructionWord::Dvi { .. }, Some(b)) => {
+                let (a, lhs) = instruction.a.unpack();
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.dvi_with_overflow(rhs);
+                self.overflow = overflow;
+                self.store_value(a, result);
+            }
+            (InstructionWord::Sti { .. }, Some(b)) => {
+                let (a, _) = instruction.a.unpack();
+                let (_, value) = b.unpack();
+                self.store_value(a, value);
+                for register in [Register::I, Register::J] {
+                    let value = self.registers[register as usize].wrapping_add(1);
+                    self.write_register(register, value);
+                }
+            }
+            (InstructionWord::Std { .. }, Some(b)) => {
+                let (a, _) = instruction.a.unpack();
+                let (_, value) = b.unpack();
+                self.store_value(a, value);
+                for register in [Register::I, Register::J] {
+                    let value = self.registers[register as usize].wrapping_sub(1);
+                    self.write_register(register, value);
                 }
             }
-            InstructionWord::Mod { .. } => {
+            (InstructionWord::Mod { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
+                let (_, rhs) = b.unpack();
                 if rhs > 0 {
                     let result = lhs % rhs;
                     self.store_value(a, result);
@@ -200,104 +751,140 @@ impl<'p> DCPU16<'p> {
                     self.store_value(a, 0);
                 }
             }
-            InstructionWord::Shl { .. } => {
+            (InstructionWord::Shl { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
-                let result = lhs << rhs;
-                self.overflow = ((((lhs as u32) << (rhs as u32)) >> 16) & 0xffff) as u16;
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.shl_with_overflow(rhs);
+                self.overflow = overflow;
                 self.store_value(a, result);
             }
-            InstructionWord::Shr { .. } => {
+            (InstructionWord::Shr { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
-                let result = lhs >> rhs;
-                self.overflow = ((((lhs as u32) << 16) >> (rhs as u32)) & 0xffff) as u16;
+                let (_, rhs) = b.unpack();
+                let (result, overflow) = lhs.shr_with_overflow(rhs);
+                self.overflow = overflow;
                 self.store_value(a, result);
             }
-            InstructionWord::And { .. } => {
+            (InstructionWord::And { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
+                let (_, rhs) = b.unpack();
                 let result = lhs.bitand(rhs);
                 self.store_value(a, result);
             }
-            InstructionWord::Bor { .. } => {
+            (InstructionWord::Bor { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
+                let (_, rhs) = b.unpack();
                 let result = lhs.bitor(rhs);
                 self.store_value(a, result);
             }
-            InstructionWord::Xor { .. } => {
+            (InstructionWord::Xor { .. }, Some(b)) => {
                 let (a, lhs) = instruction.a.unpack();
-                let (_, rhs) = instruction.b.expect("require second argument").unpack();
+                let (_, rhs) = b.unpack();
                 let result = lhs.bitxor(rhs);
                 self.store_value(a, result);
             }
-            InstructionWord::Ife { .. } => {
+            (InstructionWord::Ife { .. }, Some(b)) => {
                 let lhs = instruction.a.resolved_value;
-                let rhs = instruction
-                    .b
-                    .expect("require second argument")
-                    .resolved_value;
-                if !(lhs == rhs) {
+                let rhs = b.resolved_value;
+                if lhs != rhs {
                     self.skip_next_intruction = true;
                 }
             }
-            InstructionWord::Ifn { .. } => {
+            (InstructionWord::Ifn { .. }, Some(b)) => {
                 let lhs = instruction.a.resolved_value;
-                let rhs = instruction
-                    .b
-                    .expect("require second argument")
-                    .resolved_value;
-                if !(lhs != rhs) {
+                let rhs = b.resolved_value;
+                if lhs == rhs {
                     self.skip_next_intruction = true;
                 }
             }
-            InstructionWord::Ifg { .. } => {
+            (InstructionWord::Ifg { .. }, Some(b)) => {
                 let lhs = instruction.a.resolved_value;
-                let rhs = instruction
-                    .b
-                    .expect("require second argument")
-                    .resolved_value;
-                if !(lhs > rhs) {
+                let rhs = b.resolved_value;
+                if lhs <= rhs {
                     self.skip_next_intruction = true;
                 }
             }
-            InstructionWord::Ifb { .. } => {
+            (InstructionWord::Ifb { .. }, Some(b)) => {
                 let lhs = instruction.a.resolved_value;
-                let rhs = instruction
-                    .b
-                    .expect("require second argument")
-                    .resolved_value;
-                if !(lhs.bitor(rhs) != 0) {
+                let rhs = b.resolved_value;
+                if lhs.bitor(rhs) == 0 {
+                    self.skip_next_intruction = true;
+                }
+            }
+            (InstructionWord::Ifa { .. }, Some(b)) => {
+                let lhs = instruction.a.resolved_value as i16;
+                let rhs = b.resolved_value as i16;
+                if lhs <= rhs {
                     self.skip_next_intruction = true;
                 }
             }
+            (InstructionWord::Ifl { .. }, Some(b)) => {
+                let lhs = instruction.a.resolved_value;
+                let rhs = b.resolved_value;
+                if lhs >= rhs {
+                    self.skip_next_intruction = true;
+                }
+            }
+            (InstructionWord::Ifu { .. }, Some(b)) => {
+                let lhs = instruction.a.resolved_value as i16;
+                let rhs = b.resolved_value as i16;
+                if lhs >= rhs {
+                    self.skip_next_intruction = true;
+                }
+            }
+        }
+
+        // A failed test costs an additional cycle.
+        if self.skip_next_intruction {
+            self.cycles += 1;
+        }
+
+        if self.literal_written {
+            return StepResult::LiteralWrite;
         }
 
         // An operation may mutate the program counter, e.g. `SET PC, POP`.
         // The comparison of the PC before the instruction was read and after
         // it was executed can be used as a naive heuristic for crash loop detection.
-        if self.previous_program_counter == self.program_counter {
-            warn!(
-                "Crash loop detected at PC={pc:04X} - terminating",
-                pc = self.program_counter
-            );
-            return false;
+        // A jump that wrote any other state still makes progress and is not a loop.
+        if self.previous_program_counter == self.program_counter && !self.state_written {
+            if self.halt_on_self_jump {
+                warn!(
+                    "Crash loop detected at PC={pc:04X} - terminating",
+                    pc = self.program_counter
+                );
+                return StepResult::CrashLoop;
+            }
+
+            info!("Program halted at PC={pc:04X}", pc = self.program_counter);
+            return StepResult::Halted;
         }
 
-        true
+        StepResult::Executed
     }
 
-    fn read_instruction(&mut self) -> InstructionWithOperands {
-        let raw_instruction = self.read_word_and_advance_pc();
-        let instruction_word = InstructionWord::decode(raw_instruction);
-        assert!(instruction_word.length_in_words() >= 1);
+    /// Rejects an instruction that cannot be executed, e.g. a reserved instruction.
+    ///
+    /// The program counter stays at the instruction for inspection.
+    fn reject_instruction(&mut self) -> StepResult {
+        warn!(
+            "Reserved instruction at PC={pc:04X} - terminating",
+            pc = self.previous_program_counter
+        );
+        self.program_counter = self.previous_program_counter;
+        StepResult::ReservedInstruction
+    }
 
-        let instruction = match instruction_word.length_in_words() {
-            1 => Instruction::OneWord {
-                raw_instruction,
-                instruction: instruction_word,
-            },
+    fn read_instruction(&mut self) -> InstructionWithOperands {
+        let mut raw_instruction = self.read_word_and_advance_pc();
+        let mut instruction_word = InstructionWord::decode(raw_instruction);
+        if raw_instruction == EXTENDED_PREFIX {
+            raw_instruction = self.read_word_and_advance_pc();
+            instruction_word = InstructionWord::decode_extended(raw_instruction);
+        }
+        // An instruction consists of the instruction word and at most two operand words.
+        let length = instruction_word.length_in_words() - instruction_word.num_prefix_words();
+        let instruction = match length {
             2 => Instruction::TwoWord {
                 raw_instruction,
                 instruction: instruction_word,
@@ -309,43 +896,40 @@ impl<'p> DCPU16<'p> {
                 raw_1st: self.read_word_and_advance_pc(),
                 raw_2nd: self.read_word_and_advance_pc(),
             },
-            _ => unreachable!(),
+            _ => Instruction::OneWord {
+                raw_instruction,
+                instruction: instruction_word,
+            },
         };
 
-        InstructionWithOperands::resolve(self, instruction)
+        // A skipped instruction must not have side effects, e.g. through PUSH or POP.
+        if self.skip_next_intruction {
+            InstructionWithOperands::resolve_static(instruction)
+        } else {
+            InstructionWithOperands::resolve(self, instruction)
+        }
     }
 
     /// Reads the value at the current program counter and advances the program counter.
     fn read_word_and_advance_pc(&mut self) -> u16 {
-        let value = self.program[self.program_counter as usize];
-        self.program_counter += 1;
+        let value = self.ram[self.ram_index(self.program_counter)];
+        self.program_counter = self.program_counter.wrapping_add(1);
         value
     }
 
-    /// Shorthand for [`interpret_argument()`] followed by [`read_value()`].
-    /// Returns the address and the value at the address.
-    fn resolve_argument(
-        &mut self,
-        value: InstructionArgumentDefinition,
-        operand: Option<Word>,
-    ) -> (InstructionArgument, Word) {
-        let argument = InstructionArgument::from(value, operand);
-        (argument, self.read_value(argument))
-    }
-
     /// Reads the value from the specified argument.
     fn read_value(&mut self, address: InstructionArgument) -> Word {
         match address {
             InstructionArgument::Literal(value) => value,
             InstructionArgument::Register(register) => self.registers[register as usize],
-            InstructionArgument::Address(address) => self.ram[address as usize],
+            InstructionArgument::Address(address) => self.read_memory(address),
             InstructionArgument::AddressFromRegister(register) => {
                 let address = self.registers[register as usize];
-                self.ram[address as usize]
+                self.read_memory(address)
             }
             InstructionArgument::AddressOffset { address, register } => {
                 let offset = self.registers[register as usize];
-                self.ram[address as usize + offset as usize]
+                self.read_memory(address.wrapping_add(offset))
             }
             InstructionArgument::SpecialRegister(register) => match register {
                 SpecialRegister::ProgramCounter => self.program_counter,
@@ -353,17 +937,19 @@ impl<'p> DCPU16<'p> {
                 SpecialRegister::Overflow => self.overflow,
             },
             InstructionArgument::StackOperation(operation) => match operation {
-                StackOperation::Peek => self.ram[self.stack_pointer as usize],
+                StackOperation::Peek => self.read_memory(self.stack_pointer),
                 StackOperation::Pop => {
                     let address = self.stack_pointer;
-                    self.stack_pointer += 1;
-                    self.ram[address as usize]
+                    self.stack_pointer = self.stack_pointer.wrapping_add(1);
+                    self.state_written = true;
+                    self.read_memory(address)
                 }
                 StackOperation::Push => {
                     warn!("Detected write from a PUSH");
-                    self.stack_pointer -= 1;
+                    self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+                    self.state_written = true;
                     let address = self.stack_pointer;
-                    self.ram[address as usize]
+                    self.read_memory(address)
                 }
             },
         }
@@ -371,25 +957,41 @@ impl<'p> DCPU16<'p> {
 
     /// Stores the value to the specified address.
     fn store_value(&mut self, address: InstructionArgument, value: Word) {
+        let writes_state = !matches!(
+            address,
+            InstructionArgument::Literal(_)
+                | InstructionArgument::SpecialRegister(SpecialRegister::ProgramCounter)
+        );
+        self.state_written |= writes_state;
+
         match address {
             // Specification:
             // If any instruction tries to assign a literal value, the assignment fails silently.
             // Other than that, the instruction behaves as normal.
-            InstructionArgument::Literal(_) => {
-                trace!(
+            InstructionArgument::Literal(literal) => match self.literal_write_policy {
+                LiteralWritePolicy::Ignore => trace!(
                     "Skipping literal assignment of word {word:04X} to literal {literal:04X}",
                     word = value,
-                    literal = address.get_literal().unwrap()
-                )
-            }
-            InstructionArgument::Register(register) => self.registers[register as usize] = value,
-            InstructionArgument::Address(address) => self.ram[address as usize] = value,
+                    literal = literal
+                ),
+                LiteralWritePolicy::Warn | LiteralWritePolicy::Error => {
+                    warn!(
+                        "Skipping literal assignment of word {word:04X} to literal {literal:04X} at PC={pc:04X}",
+                        word = value,
+                        literal = literal,
+                        pc = self.previous_program_counter
+                    );
+                    self.literal_written = self.literal_write_policy == LiteralWritePolicy::Error;
+                }
+            },
+            InstructionArgument::Register(register) => self.write_register(register, value),
+            InstructionArgument::Address(address) => self.write_address(address, value),
             InstructionArgument::AddressFromRegister(register) => {
-                self.ram[self.registers[register as usize] as usize] = value
+                self.write_address(self.registers[register as usize], value)
             }
             InstructionArgument::AddressOffset { address, register } => {
                 let register_value = self.registers[register as usize];
-                self.ram[address as usize + register_value as usize] = value
+                self.write_address(address.wrapping_add(register_value), value)
             }
             InstructionArgument::SpecialRegister(register) => match register {
                 SpecialRegister::ProgramCounter => self.program_counter = value,
@@ -399,23 +1001,32 @@ impl<'p> DCPU16<'p> {
             InstructionArgument::StackOperation(register) => match register {
                 StackOperation::Peek => {
                     warn!("Detected write to a PEEK");
-                    self.ram[self.stack_pointer as usize] = value
+                    self.write_memory(self.stack_pointer, value)
                 }
+                // The stack pointer was already moved when the operand was resolved.
                 StackOperation::Pop => {
                     warn!("Detected write to a POP");
-                    let address = self.stack_pointer;
-                    self.stack_pointer += 1;
-                    self.ram[address as usize] = value
-                }
-                StackOperation::Push => {
-                    self.stack_pointer -= 1;
-                    let address = self.stack_pointer;
-                    self.ram[address as usize] = value
+                    self.write_memory(self.stack_pointer.wrapping_sub(1), value)
                 }
+                StackOperation::Push => self.write_memory(self.stack_pointer, value),
             },
         }
     }
 
+    /// Writes the value to the address of an address operand, raising the high-water mark.
+    fn write_address(&mut self, address: Word, value: Word) {
+        self.high_water = self.high_water.max(address);
+        self.write_ram(address, value)
+    }
+
+    /// Gets the highest RAM address written by an instruction through an address operand,
+    /// e.g. to only dump the used part of the RAM. Writes to the stack are not considered.
+    ///
+    /// Returns zero if no such write happened.
+    pub fn high_water_mark(&self) -> Word {
+        self.high_water
+    }
+
     fn dump_registers(&self) {
         debug!(
             "Registers: A={a:04X?} B={b:04X?} C={c:04X?} X={x:04X?} Y={y:04X?} Z={z:04X?} I={i:04X?} J={j:04X?} PC⁎={pc:04X?} SP={sp:04X?} O={o:04X?}",
@@ -434,72 +1045,1081 @@ impl<'p> DCPU16<'p> {
     }
 
     pub fn hexdump_program(&self, words_per_row: usize) -> String {
+        hexdump_words(0, &self.program, words_per_row)
+    }
+
+    pub fn hexdump_ram(&self, words_per_row: usize) -> String {
+        hexdump_words(0, &self.ram, words_per_row)
+    }
+
+    /// Dumps the RAM from `start` up to, but excluding, `end`.
+    ///
+    /// Rows start at `start`, not at multiples of `words_per_row`.
+    /// Returns an empty string if `start` is not below `end`.
+    pub fn hexdump_range(&self, start: Word, end: Word, words_per_row: usize) -> String {
+        let words: Vec<Word> = (start..end)
+            .map(|address| self.ram[self.ram_index(address)])
+            .collect();
+        hexdump_words(start as usize, &words, words_per_row)
+    }
+
+    /// Like [`DCPU16::hexdump_ram`], but runs of rows containing only zeros are collapsed
+    /// in the style of `hexdump -C`: the first row of a run is kept and the following
+    /// rows are replaced by a single `*` line. The last row is always kept.
+    pub fn hexdump_ram_compact(&self, words_per_row: usize) -> String {
         assert!(words_per_row > 0);
-        let newline = String::from('\n');
-        let length_of_newline = newline.len();
-        debug_assert_eq!(length_of_newline, 1);
+        let last_row = self.ram.len().div_ceil(words_per_row) - 1;
+        let mut dump = String::new();
+        let mut eliding = false;
+        let mut previous_is_zero = false;
 
-        let row_length = (4 + 1) + (1 + 4) * words_per_row + length_of_newline;
-        let row_count = self.program.len() / words_per_row;
-        let remainder = self.program.len() % words_per_row;
-        let mut expected_num_characters = row_length * row_count;
-        if remainder > 0 {
-            expected_num_characters += row_length;
-        }
+        for (row, words) in self.ram.chunks(words_per_row).enumerate() {
+            let is_zero = words.iter().all(|&word| word == 0);
+            let elide = is_zero && previous_is_zero && row != last_row;
+            previous_is_zero = is_zero;
 
-        let mut dump = String::with_capacity(expected_num_characters);
+            if elide {
+                if !eliding {
+                    dump.push_str("*\n");
+                    eliding = true;
+                }
+                continue;
+            }
 
-        for row in 0..row_count {
-            let row_start = row * words_per_row;
-            dump.push_str(format!("{:04X}:", row_start).as_str());
-            for word in 0..words_per_row {
-                dump.push_str(format!(" {:04X}", self.program[row_start + word]).as_str());
+            eliding = false;
+            dump.push_str(format!("{:04X}:", row * words_per_row).as_str());
+            for word in words {
+                dump.push_str(format!(" {:04X}", word).as_str());
             }
-            dump.push_str(newline.as_str())
+            for _ in words.len()..words_per_row {
+                dump.push_str("     ");
+            }
+            dump.push('\n');
         }
 
-        if remainder > 0 {
-            for row in row_count..(row_count + 1) {
-                let row_start = row * words_per_row;
-                dump.push_str(format!("{:04X}:", row_start).as_str());
-                for word in 0..remainder {
-                    dump.push_str(format!(" {:04X}", self.program[row_start + word]).as_str());
-                }
-                for _ in remainder..words_per_row {
-                    dump.push_str("     ");
-                }
+        dump
+    }
+}
+
+/// Dumps the words in rows of `words_per_row`, each prefixed with the address of its first word.
+/// An incomplete last row is padded with spaces.
+fn hexdump_words(first_address: usize, words: &[Word], words_per_row: usize) -> String {
+    assert!(words_per_row > 0);
+    let newline = String::from('\n');
+    let length_of_newline = newline.len();
+    debug_assert_eq!(length_of_newline, 1);
+
+    let row_length = (4 + 1) + (1 + 4) * words_per_row + length_of_newline;
+    let row_count = words.len() / words_per_row;
+    let remainder = words.len() % words_per_row;
+    let mut expected_num_characters = row_length * row_count;
+    if remainder > 0 {
+        expected_num_characters += row_length;
+    }
+
+    let mut dump = String::with_capacity(expected_num_characters);
+
+    for (row, words) in words.chunks(words_per_row).enumerate() {
+        let row_start = first_address + row * words_per_row;
+        dump.push_str(format!("{:04X}:", row_start).as_str());
+        for word in words {
+            dump.push_str(format!(" {:04X}", word).as_str());
+        }
+        for _ in words.len()..words_per_row {
+            dump.push_str("     ");
+        }
+        dump.push_str(newline.as_str())
+    }
+
+    assert_eq!(dump.len(), expected_num_characters);
+    dump
+}
+
+impl<'p> From<&'p [u16]> for DCPU16<'p> {
+    fn from(program: &'p [u16]) -> Self {
+        Self::new(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SAMPLE_PROGRAM;
+
+    #[test]
+    fn sample_program_state_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.run();
+
+        // The arithmetic through CpuWord leaves the final state unchanged.
+        assert_eq!(cpu.registers, [0x2000, 0, 0, 0x0040, 0, 0, 0, 0]);
+        assert_eq!(cpu.program_counter, 0x001A);
+        assert_eq!(cpu.stack_pointer, 0xffff);
+        assert_eq!(cpu.overflow, 0x0000);
+        assert_eq!(cpu.cycles(), 104);
+
+        let mut ram = vec![0x0000; NUM_RAM_WORDS];
+        ram[..SAMPLE_PROGRAM.len()].copy_from_slice(&SAMPLE_PROGRAM);
+        ram[0x1000] = 0x0020;
+        // The return address of JSR testsub.
+        ram[0xfffe] = 0x0016;
+        assert_eq!(cpu.ram(), &ram[..]);
+    }
+
+    #[test]
+    fn cycles_start_at_zero() {
+        let cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        assert_eq!(cpu.cycles(), 0);
+    }
+
+    #[test]
+    fn cycles_of_sample_program_work() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        // SET A, 0x30 takes 1 cycle plus 1 for the next word literal.
+        cpu.step();
+        assert_eq!(cpu.cycles(), 2);
+
+        // SET [0x1000], 0x20 takes 1 cycle plus 1 for each next word.
+        cpu.step();
+        assert_eq!(cpu.cycles(), 5);
+
+        cpu.run();
+        assert_eq!(cpu.cycles(), 104);
+    }
+
+    #[test]
+    fn disabled_diagnostics_do_not_change_execution() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        let expected = cpu.run_with_limit(u64::MAX);
+
+        let mut quiet = DCPU16::new(&SAMPLE_PROGRAM);
+        quiet.set_diagnostics(false);
+        assert_eq!(quiet.run_with_limit(u64::MAX), expected);
+        assert!(quiet.snapshot() == cpu.snapshot());
+        assert_eq!(quiet.executed_count(), cpu.executed_count());
+    }
+
+    #[test]
+    fn ram_slice_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.set_ram(0x2000, 0x1234);
+        cpu.run();
+
+        // The loop copies the word at 0x2000 to 0x2001..=0x200A.
+        let buffer = cpu.ram_slice(0x2000, 12).unwrap();
+        assert!(buffer[..11].iter().all(|&word| word == 0x1234));
+        assert_eq!(buffer[11], 0x0000);
+
+        assert_eq!(cpu.ram_slice(0xfffe, 2).map(|slice| slice.len()), Some(2));
+        assert_eq!(cpu.ram_slice(0xffff, 2), None);
+    }
+
+    #[test]
+    fn executed_and_skipped_counts_work() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        assert_eq!(cpu.executed_count(), 0);
+        assert_eq!(cpu.skipped_count(), 0);
+
+        // SET A, 0x30; SET [0x1000], 0x20; SUB A, [0x1000]; IFN A, 0x10 (fails)
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.executed_count(), 4);
+        assert_eq!(cpu.skipped_count(), 0);
+
+        // SET PC, 0x1A
+        assert_eq!(cpu.step(), StepResult::Skipped);
+        assert_eq!(cpu.executed_count(), 4);
+        assert_eq!(cpu.skipped_count(), 1);
+
+        cpu.reset();
+        assert_eq!(cpu.executed_count(), 0);
+        assert_eq!(cpu.skipped_count(), 0);
+    }
+
+    #[test]
+    fn small_ram_wraps_around() {
+        // SET [0x1010], 0x20
+        // SET A, [0x0010]
+        // SET PEEK, 0x1234
+        let program = [0x7de1, 0x1010, 0x0020, 0x7801, 0x0010, 0x7d91, 0x1234];
+        let mut cpu = DCPU16::with_ram_words(&program, 256);
+        assert_eq!(cpu.ram().len(), 256);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::EndOfProgram);
+
+        assert_eq!(cpu.ram()[0x10], 0x0020);
+        assert_eq!(cpu.register(Register::A), 0x0020);
+        assert_eq!(cpu.stack_pointer, 0xffff);
+        assert_eq!(cpu.ram()[0xff], 0x1234);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn ram_size_must_be_a_power_of_two() {
+        let _ = DCPU16::with_ram_words(&[], 100);
+    }
+
+    #[test]
+    fn reserved_instruction_stops_execution() {
+        // SET A, 1
+        // (reserved)
+        let program = [0x8401, 0x0000, 0x8401];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.step(), StepResult::Executed);
+        assert_eq!(cpu.step(), StepResult::ReservedInstruction);
+        assert_eq!(cpu.program_counter, 0x0001);
+
+        cpu.reset();
+        assert_eq!(
+            cpu.run_with_limit(u64::MAX),
+            RunOutcome::ReservedInstruction { pc: 0x0001 }
+        );
+        assert_eq!(cpu.program_counter, 0x0001);
+        assert_eq!(cpu.executed_count(), 1);
+    }
+
+    #[test]
+    fn stack_operands_resolve_in_order() {
+        // SET PUSH, 1
+        // SET PUSH, 2
+        // SET A, POP
+        // SET B, POP
+        let program = [0x85a1, 0x89a1, 0x6001, 0x6011];
+        let mut cpu = DCPU16::new(&program);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.stack_pointer, 0xfffd);
+        assert_eq!(cpu.ram()[0xfffd..0xffff], [0x0002, 0x0001]);
+
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::EndOfProgram);
+        assert_eq!(cpu.register(Register::A), 0x02);
+        assert_eq!(cpu.register(Register::B), 0x01);
+        assert_eq!(cpu.stack_pointer, STACK_POINTER_INIT as Word);
+
+        // "a" pops before "b", i.e. this tests 2 > 1.
+        // SET PUSH, 1
+        // SET PUSH, 2
+        // IFG POP, POP
+        // SET C, 1
+        let program = [0x85a1, 0x89a1, 0x618e, 0x8421];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::EndOfProgram);
+        assert_eq!(cpu.register(Register::C), 0x01);
+        assert_eq!(cpu.stack_pointer, STACK_POINTER_INIT as Word);
+    }
+
+    #[test]
+    fn skipped_stack_operands_have_no_effect() {
+        // IFE A, 1 (fails)
+        // SET PUSH, 1 (skipped)
+        // SET PUSH, 2
+        // IFE A, 1 (fails)
+        // SET B, POP (skipped)
+        let program = [0x840c, 0x85a1, 0x89a1, 0x840c, 0x6011];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::EndOfProgram);
+        assert_eq!(cpu.stack_pointer, 0xfffe);
+        assert_eq!(cpu.ram()[0xfffe], 0x0002);
+        assert_eq!(cpu.register(Register::B), 0x00);
+    }
+
+    #[test]
+    fn skipped_reserved_instruction_works() {
+        // IFE A, 1 (fails)
+        // (reserved, skipped)
+        // SET B, 1
+        let program = [0x840c, 0x0000, 0x8411];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::EndOfProgram);
+        assert_eq!(cpu.register(Register::B), 0x01);
+    }
+
+    #[test]
+    fn chained_tests_are_skipped() {
+        // IFE A, 1 (fails)
+        // IFE B, 0 (skipped)
+        // SET C, 1 (skipped)
+        // SET X, 1
+        let program = [0x840c, 0x801c, 0x8421, 0x8431];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::EndOfProgram);
+        assert_eq!(cpu.register(Register::C), 0x00);
+        assert_eq!(cpu.register(Register::X), 0x01);
+        assert_eq!(cpu.executed_count(), 2);
+        assert_eq!(cpu.skipped_count(), 2);
+    }
+
+    #[test]
+    fn cycles_of_failed_test_work() {
+        // IFN A, 0x00 (fails), SET B, 0x01 (skipped), SET PC, 0x03
+        let program = [0x800d, 0x8411, 0x8dc1, 0x8dc1];
+        let mut cpu = DCPU16::new(&program);
+
+        // The test fails, which costs an extra cycle.
+        cpu.step();
+        assert_eq!(cpu.cycles(), 3);
+
+        // Skipped instructions don't consume cycles on their own.
+        cpu.step();
+        assert_eq!(cpu.cycles(), 3);
+    }
+
+    #[test]
+    fn run_with_limit_detects_crash_loop() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        assert_eq!(
+            cpu.run_with_limit(u64::MAX),
+            RunOutcome::CrashLoop { pc: 0x001A }
+        );
+        assert_eq!(cpu.program_counter, 0x001A);
+    }
+
+    #[test]
+    fn run_to_completion_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        assert_eq!(
+            cpu.run_to_completion(),
+            RunOutcome::CrashLoop { pc: 0x001A }
+        );
+
+        // HLT
+        let mut cpu = DCPU16::new(&[0x83d0]);
+        assert_eq!(cpu.run_to_completion(), RunOutcome::Halted);
+    }
+
+    #[test]
+    fn run_with_limit_detects_end_of_program() {
+        // SET A, 0x01
+        let program = [0x8401];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::EndOfProgram);
+        assert_eq!(cpu.register(Register::A), 0x01);
+    }
+
+    #[test]
+    fn run_cycles_stops_before_exceeding_budget() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        // SET A, 0x30 (2 cycles), SET [0x1000], 0x20 (3 cycles), SUB A, [0x1000] (3 cycles)
+        assert_eq!(cpu.run_cycles(7), RunOutcome::CycleLimitReached);
+        assert_eq!(cpu.cycles(), 5);
+        assert_eq!(cpu.program_counter, 0x0005);
+
+        assert_eq!(cpu.run_cycles(3), RunOutcome::CycleLimitReached);
+        assert_eq!(cpu.cycles(), 8);
+        assert_eq!(cpu.program_counter, 0x0007);
+
+        // IFN A, 0x10 (2 cycles and a penalty cycle) is budgeted as a failed test.
+        assert_eq!(cpu.run_cycles(2), RunOutcome::CycleLimitReached);
+        assert_eq!(cpu.program_counter, 0x0007);
+        assert_eq!(cpu.run_cycles(3), RunOutcome::CycleLimitReached);
+        assert_eq!(cpu.cycles(), 11);
+
+        // Execution can be resumed.
+        assert_eq!(
+            cpu.run_cycles(u64::MAX),
+            RunOutcome::CrashLoop { pc: 0x001A }
+        );
+    }
+
+    #[test]
+    fn run_with_limit_stops_after_max_steps() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        assert_eq!(cpu.run_with_limit(3), RunOutcome::StepLimitReached);
+        assert_eq!(cpu.program_counter, 0x0007);
+
+        // Execution can be resumed.
+        assert_eq!(
+            cpu.run_with_limit(u64::MAX),
+            RunOutcome::CrashLoop { pc: 0x001A }
+        );
+    }
+
+    #[test]
+    fn step_results_work() {
+        // IFN A, 0x00 (fails), SET B, 0x01 (skipped), SET PC, 0x03, SET PC, 0x03
+        let program = [0x800d, 0x8411, 0x8dc1, 0x8dc1];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.step(), StepResult::Executed);
+        assert_eq!(cpu.step(), StepResult::Skipped);
+        assert_eq!(cpu.step(), StepResult::Executed);
+        assert_eq!(cpu.step(), StepResult::CrashLoop);
+    }
+
+    #[test]
+    fn self_jump_is_crash_loop_by_default() {
+        // :here SET PC, here
+        let program = [0x81c1, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.step(), StepResult::CrashLoop);
+    }
+
+    #[test]
+    #[cfg(feature = "assembler")]
+    fn hlt_halts_the_cpu() {
+        let program = assemble(
+            "SET A, 1
+            HLT
+            SET A, 2",
+        )
+        .unwrap();
+        assert_eq!(program, vec![0x8401, 0x83d0, 0x8801]);
+
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::Halted);
+        assert_eq!(cpu.register(Register::A), 0x01);
+        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.cycles(), 2);
+
+        // The CPU stays halted until it is reset.
+        assert_eq!(cpu.step(), StepResult::Halted);
+        assert_eq!(cpu.program_counter, 0x0002);
+        cpu.reset();
+        assert_eq!(cpu.step(), StepResult::Executed);
+    }
+
+    #[test]
+    #[cfg(feature = "assembler")]
+    fn sti_copies_a_buffer() {
+        let program = assemble(
+            "SET I, 0x1000
+            SET J, 0x2000
+            :loop STI [J], [I]
+            IFN I, 0x1004
+            SET PC, loop
+            HLT",
+        )
+        .unwrap();
+
+        let mut cpu = DCPU16::new(&program);
+        cpu.ram_mut()[0x1000..0x1004].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::Halted);
+        assert_eq!(cpu.ram()[0x2000..0x2005], [1, 2, 3, 4, 0]);
+        assert_eq!(cpu.register(Register::I), 0x1004);
+        assert_eq!(cpu.register(Register::J), 0x2004);
+    }
+
+    #[test]
+    fn std_decrements_i_and_j() {
+        // STD A, [I]
+        let mut cpu = DCPU16::new(&[0x03e0, 0x3802]);
+        cpu.set_register(Register::I, 0x0010);
+        cpu.ram_mut()[0x0010] = 0x1234;
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x1234);
+        assert_eq!(cpu.register(Register::I), 0x000f);
+        assert_eq!(cpu.register(Register::J), 0xffff);
+    }
+
+    #[test]
+    fn borrowed_ram_works() {
+        let mut owned = DCPU16::new(&SAMPLE_PROGRAM);
+        owned.run();
+
+        let mut buffer = Box::new([0xffff; NUM_RAM_WORDS]);
+        let mut borrowed = DCPU16::with_ram(&SAMPLE_PROGRAM, &mut buffer);
+        borrowed.run();
+
+        assert!(borrowed.diff(&owned).is_empty());
+        drop(borrowed);
+        assert_eq!(buffer[..], owned.ram()[..]);
+    }
+
+    #[test]
+    fn load_program_works() {
+        // SET A, 0x01
+        // IFE A, 0x01
+        // HLT
+        let first = [0x8401, 0x840c, 0x03d0];
+        // SET B, 0x02
+        let second = [0x8811];
+
+        let mut cpu = DCPU16::new(&first);
+        assert_eq!(cpu.run_until_break(), RunOutcome::Halted);
+
+        cpu.load_program(&second);
+        assert_eq!(cpu.program_counter, 0x0000);
+        assert!(!cpu.skip_next_intruction);
+        assert_eq!(cpu.ram()[..3], [0x8811, 0x840c, 0x03d0]);
+
+        assert_eq!(cpu.step(), StepResult::EndOfProgram);
+        assert_eq!(cpu.register(Register::A), 0x0001);
+        assert_eq!(cpu.register(Register::B), 0x0002);
+    }
+
+    /// A deterministic xorshift generator for arbitrary words.
+    fn random_words(seed: u32) -> impl Iterator<Item = Word> {
+        let mut state = seed.max(1);
+        std::iter::repeat_with(move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 16) as Word
+        })
+    }
+
+    /// Executes the instruction word with the operand words on the CPU.
+    fn execute_word(cpu: &mut DCPU16, words: [Word; 3]) -> StepResult {
+        for (address, &word) in words.iter().enumerate() {
+            cpu.set_ram(address as Word, word);
+        }
+        cpu.program_counter = 0x0000;
+        cpu.skip_next_intruction = false;
+        cpu.halted = false;
+        cpu.step()
+    }
 
-                dump.push_str(newline.as_str())
+    #[test]
+    fn arbitrary_instruction_words_do_not_panic() {
+        let mut cpu = DCPU16::new(&[0x0000; 3]);
+        let mut operands = random_words(0x1234);
+        for word in 0..=Word::MAX {
+            for register in 0..8 {
+                cpu.set_register(Register::from(register), operands.next().unwrap());
             }
+            cpu.stack_pointer = operands.next().unwrap();
+            execute_word(
+                &mut cpu,
+                [word, operands.next().unwrap(), operands.next().unwrap()],
+            );
         }
+    }
 
-        assert_eq!(dump.len(), expected_num_characters);
-        dump
+    #[test]
+    fn arbitrary_programs_do_not_panic() {
+        // The programs are deterministic, hence so is the way each run terminates.
+        let expected = [
+            RunOutcome::ReservedInstruction { pc: 0x0009 },
+            RunOutcome::ReservedInstruction { pc: 0x0001 },
+            RunOutcome::ReservedInstruction { pc: 0x000e },
+            RunOutcome::StepLimitReached,
+            RunOutcome::ReservedInstruction { pc: 0x0066 },
+            RunOutcome::ReservedInstruction { pc: 0x0004 },
+            RunOutcome::ReservedInstruction { pc: 0x0801 },
+            RunOutcome::ReservedInstruction { pc: 0x0004 },
+            RunOutcome::ReservedInstruction { pc: 0x0818 },
+            RunOutcome::ReservedInstruction { pc: 0x0017 },
+            RunOutcome::ReservedInstruction { pc: 0x0018 },
+            RunOutcome::ReservedInstruction { pc: 0x0004 },
+            RunOutcome::ReservedInstruction { pc: 0x0006 },
+            RunOutcome::ReservedInstruction { pc: 0x0004 },
+            RunOutcome::StepLimitReached,
+            RunOutcome::Halted,
+        ];
+
+        for (seed, expected) in (1..).zip(expected.iter()) {
+            let program: Vec<Word> = random_words(seed).take(0x1000).collect();
+            let mut cpu = DCPU16::new(&program);
+            cpu.interrupt_address = 0x0800;
+            cpu.add_device(Box::new(Clock::with_period(1)));
+            cpu.add_device(Box::new(Lem1802::new()));
+
+            assert_eq!(cpu.run_with_limit(10_000), *expected, "seed {}", seed);
+        }
     }
 
-    pub fn hexdump_ram(&self, words_per_row: usize) -> String {
-        assert!(words_per_row > 0);
-        debug_assert_eq!(NUM_RAM_WORDS, 65536);
-        let newline = String::from('\n');
-        let length_of_newline = newline.len();
-        debug_assert_eq!(length_of_newline, 1);
+    #[test]
+    fn large_shifts_work() {
+        for &(count, overflow) in [(16, 0x8001), (31, 0x8000), (32, 0x0000), (48, 0x0000)].iter() {
+            // SET A, 0x8001
+            // SHL A, count
+            let program = [0x7c01, 0x8001, 0x7c07, count];
+            let mut cpu = DCPU16::new(&program);
+            cpu.step_n(2);
+            assert_eq!(cpu.register(Register::A), 0x0000);
+            assert_eq!(cpu.overflow, overflow, "SHL by {}", count);
+        }
+    }
+
+    #[test]
+    fn signed_tests_work() {
+        // Indicates whether the next instruction is performed, given A=0xFFFF.
+        let performs_next = |program: &[Word]| {
+            let mut cpu = DCPU16::new(program);
+            cpu.set_register(Register::A, 0xffff);
+            cpu.step();
+            !cpu.skip_next_intruction
+        };
 
-        let row_length = (4 + 1) + (1 + 4) * words_per_row + length_of_newline;
-        let row_count = NUM_RAM_WORDS / words_per_row;
-        let expected_num_characters = row_length * row_count;
+        // IFG A, 1
+        assert!(performs_next(&[0x840e]));
+        // IFA A, 1
+        assert!(!performs_next(&[EXTENDED_PREFIX, 0x840c]));
+        // IFL A, 1
+        assert!(!performs_next(&[EXTENDED_PREFIX, 0x840d]));
+        // IFU A, 1
+        assert!(performs_next(&[EXTENDED_PREFIX, 0x840e]));
+    }
+
+    #[test]
+    fn self_jump_halts_if_configured() {
+        // SET A, 0x01
+        // :here SET PC, here
+        let program = [0x8401, 0x85c1, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_halt_on_self_jump(false);
+        assert_eq!(cpu.run_with_limit(u64::MAX), RunOutcome::Halted);
+        assert_eq!(cpu.program_counter, 0x0001);
+    }
 
-        let mut dump = String::with_capacity(expected_num_characters);
+    #[test]
+    fn self_jump_with_state_change_is_no_loop() {
+        // SET PUSH, 0x00
+        // SET PUSH, 0x02
+        // :here SET PC, POP ; jumps to itself once, then to 0x0000
+        let program = [0x81a1, 0x89a1, 0x61c1, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.step();
+        cpu.step();
 
-        for row in 0..row_count {
-            let row_start = row * words_per_row;
-            dump.push_str(format!("{:04X}:", row_start).as_str());
-            for word in 0..words_per_row {
-                dump.push_str(format!(" {:04X}", self.ram[row_start + word]).as_str());
+        // The stack pointer changed, so this is not considered a loop.
+        assert_eq!(cpu.step(), StepResult::Executed);
+        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.step(), StepResult::Executed);
+        assert_eq!(cpu.program_counter, 0x0000);
+        assert_eq!(cpu.run_with_limit(10), RunOutcome::StepLimitReached);
+    }
+
+    #[test]
+    fn step_result_at_end_of_program_works() {
+        // SET A, 0x01
+        let program = [0x8401];
+        let mut cpu = DCPU16::new(&program);
+        assert_eq!(cpu.step(), StepResult::EndOfProgram);
+    }
+
+    #[test]
+    fn reset_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.run();
+
+        let registers = cpu.registers;
+        let ram = cpu.ram().to_vec();
+        let (pc, sp, o, cycles) = (
+            cpu.program_counter,
+            cpu.stack_pointer,
+            cpu.overflow,
+            cpu.cycles(),
+        );
+
+        cpu.reset();
+        assert_eq!(cpu.registers, [0; NUM_REGISTERS]);
+        assert_eq!(cpu.ram()[..SAMPLE_PROGRAM.len()], SAMPLE_PROGRAM);
+        assert!(cpu.ram()[SAMPLE_PROGRAM.len()..]
+            .iter()
+            .all(|&word| word == 0));
+        assert_eq!(cpu.program_counter, 0);
+        assert_eq!(cpu.stack_pointer, STACK_POINTER_INIT as Word);
+        assert_eq!(cpu.overflow, 0);
+        assert_eq!(cpu.cycles(), 0);
+
+        cpu.run();
+        assert_eq!(cpu.registers, registers);
+        assert_eq!(cpu.ram().to_vec(), ram);
+        assert_eq!(cpu.program_counter, pc);
+        assert_eq!(cpu.stack_pointer, sp);
+        assert_eq!(cpu.overflow, o);
+        assert_eq!(cpu.cycles(), cycles);
+    }
+
+    #[test]
+    fn set_register_and_ram_works() {
+        // SET B, [A]
+        let program = [0x2011, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_register(Register::A, 0x1000);
+        cpu.set_ram(0x1000, 0x1234);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::B), 0x1234);
+    }
+
+    #[test]
+    fn register_by_name_works() {
+        let mut cpu = DCPU16::new(&[]);
+        cpu.set_register(Register::A, 0x1234);
+        cpu.set_register(Register::J, 0x5678);
+        assert_eq!(cpu.register_by_name("A"), Some(0x1234));
+        assert_eq!(cpu.register_by_name("j"), Some(0x5678));
+        assert_eq!(cpu.register_by_name("B"), Some(0x0000));
+        assert_eq!(cpu.register_by_name("SP"), None);
+    }
+
+    /// Executes the single instruction `instruction` with `A` and `B` preset,
+    /// then returns the values of `A` and `O`.
+    fn execute_with_a_and_b(instruction: Word, a: Word, b: Word) -> (Word, Word) {
+        let program = [instruction, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_register(Register::A, a);
+        cpu.set_register(Register::B, b);
+        cpu.step();
+        (cpu.register(Register::A), cpu.overflow)
+    }
+
+    /// Like [`execute_with_a_and_b`], but executes an extended instruction.
+    fn execute_extended_with_a_and_b(instruction: Word, a: Word, b: Word) -> (Word, Word) {
+        let program = [EXTENDED_PREFIX, instruction, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_register(Register::A, a);
+        cpu.set_register(Register::B, b);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0002);
+        (cpu.register(Register::A), cpu.overflow)
+    }
+
+    #[test]
+    fn add_and_sub_overflow_works() {
+        // ADD A, B
+        assert_eq!(
+            execute_with_a_and_b(0x0402, 0xffff, 0x0002),
+            (0x0001, 0x0001)
+        );
+        assert_eq!(
+            execute_with_a_and_b(0x0402, 0x0001, 0x0002),
+            (0x0003, 0x0000)
+        );
+        // SUB A, B
+        assert_eq!(
+            execute_with_a_and_b(0x0403, 0x0001, 0x0002),
+            (0xffff, 0xffff)
+        );
+        assert_eq!(
+            execute_with_a_and_b(0x0403, 0x0002, 0x0001),
+            (0x0001, 0x0000)
+        );
+    }
+
+    #[test]
+    fn overflow_is_visible_to_the_next_instruction() {
+        // ADD A, B
+        // ADD C, O
+        // SUB X, B
+        // ADD Y, O
+        let program = [0x0402, 0x7422, 0x0433, 0x7442, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_register(Register::A, 0xffff);
+        cpu.set_register(Register::B, 0x0001);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x0000);
+        assert_eq!(cpu.register(Register::C), 0x0001);
+        assert_eq!(cpu.overflow, 0x0000);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.register(Register::X), 0xffff);
+        assert_eq!(cpu.register(Register::Y), 0xffff);
+    }
+
+    #[test]
+    fn overflow_matches_both_specifications() {
+        // The 1.1 and 1.7 specifications use the same formulas, e.g. for DIV:
+        // O = ((a<<16)/b)&0xffff, respectively EX = ((b<<16)/a)&0xffff.
+        for &(a, b) in &[(0x0001, 0x0003), (0xffff, 0x0002), (0x1234, 0x0007)] {
+            let div = ((((a as u32) << 16) / (b as u32)) & 0xffff) as Word;
+            let shl = ((((a as u32) << b) >> 16) & 0xffff) as Word;
+            let shr = ((((a as u32) << 16) >> b) & 0xffff) as Word;
+            // DIV A, B
+            assert_eq!(execute_with_a_and_b(0x0405, a, b).1, div);
+            // SHL A, B
+            assert_eq!(execute_with_a_and_b(0x0407, a, b).1, shl);
+            // SHR A, B
+            assert_eq!(execute_with_a_and_b(0x0408, a, b).1, shr);
+        }
+    }
+
+    #[test]
+    fn spec_version_works() {
+        // DIV A, B, SHL A, B and SHR A, B, each after SET O, 0x1F
+        for &(instruction, a, b, expected) in &[
+            (0x0405, 0x0001, 0x0003, (0x0000, 0x5555)),
+            (0x0405, 0x0008, 0x0002, (0x0004, 0x0000)),
+            (0x0407, 0x8001, 0x0001, (0x0002, 0x0001)),
+            (0x0407, 0x0001, 0x0001, (0x0002, 0x0000)),
+            (0x0408, 0x0003, 0x0001, (0x0001, 0x8000)),
+            (0x0408, 0x0002, 0x0001, (0x0001, 0x0000)),
+        ] {
+            // Both revisions share the formulas and clear the overflow if nothing carries.
+            for &version in &[SpecVersion::V1_1, SpecVersion::V1_7] {
+                let program = [0xfdd1, instruction];
+                let mut cpu = DCPU16::new(&program);
+                cpu.set_spec_version(version);
+                assert_eq!(cpu.spec_version(), version);
+                cpu.set_register(Register::A, a);
+                cpu.set_register(Register::B, b);
+                cpu.step_n(2);
+                let result = (cpu.register(Register::A), cpu.overflow);
+                assert_eq!(result, expected, "{:04x} {:?}", instruction, version);
             }
-            dump.push_str(newline.as_str())
         }
+    }
 
-        assert_eq!(dump.len(), expected_num_characters);
-        dump
+    #[test]
+    fn shl_by_large_amounts_works() {
+        // SHL A, B
+        assert_eq!(execute_with_a_and_b(0x0407, 0x8001, 15), (0x8000, 0x4000));
+        assert_eq!(execute_with_a_and_b(0x0407, 0x8001, 16), (0x0000, 0x8001));
+        assert_eq!(execute_with_a_and_b(0x0407, 0x8001, 17), (0x0000, 0x0002));
+        assert_eq!(execute_with_a_and_b(0x0407, 0x8001, 32), (0x0000, 0x0000));
+    }
+
+    #[test]
+    fn shr_by_large_amounts_works() {
+        // SHR A, B
+        assert_eq!(execute_with_a_and_b(0x0408, 0x8001, 15), (0x0001, 0x0002));
+        assert_eq!(execute_with_a_and_b(0x0408, 0x8001, 16), (0x0000, 0x8001));
+        assert_eq!(execute_with_a_and_b(0x0408, 0x8001, 17), (0x0000, 0x4000));
+        assert_eq!(execute_with_a_and_b(0x0408, 0x8001, 32), (0x0000, 0x0000));
+    }
+
+    #[test]
+    fn div_works() {
+        // DIV A, B
+        assert_eq!(execute_with_a_and_b(0x0405, 7, 2), (0x0003, 0x8000));
+        assert_eq!(execute_with_a_and_b(0x0405, 1, 3), (0x0000, 0x5555));
+        assert_eq!(execute_with_a_and_b(0x0405, 1, 0), (0x0000, 0x0000));
+    }
+
+    #[test]
+    fn dvi_with_negative_operands_works() {
+        // DVI A, B
+        assert_eq!(
+            execute_extended_with_a_and_b(0x0405, -7i16 as _, 2),
+            (-3i16 as _, 0x8000)
+        );
+        assert_eq!(
+            execute_extended_with_a_and_b(0x0405, 7, -2i16 as _),
+            (-3i16 as _, 0x8000)
+        );
+        assert_eq!(
+            execute_extended_with_a_and_b(0x0405, -7i16 as _, -2i16 as _),
+            (3, 0x8000)
+        );
+        assert_eq!(execute_extended_with_a_and_b(0x0405, -7i16 as _, 0), (0, 0));
+    }
+
+    #[test]
+    fn mli_with_negative_operands_works() {
+        // MLI A, B
+        assert_eq!(
+            execute_extended_with_a_and_b(0x0404, -3i16 as _, 4),
+            (-12i16 as _, 0xffff)
+        );
+        assert_eq!(
+            execute_extended_with_a_and_b(0x0404, -3i16 as _, -4i16 as _),
+            (12, 0x0000)
+        );
+        assert_eq!(
+            execute_extended_with_a_and_b(0x0404, 0x4000, 4),
+            (0x0000, 0x0001)
+        );
+    }
+
+    #[test]
+    fn from_words_works() {
+        let program = [0x7c01, 0x0030];
+        let mut cpu = DCPU16::from(&program[..]);
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x30);
+    }
+
+    #[test]
+    fn program_counter_wraps_around() {
+        // SET A, 0x1234 with the instruction at 0xFFFF and its next word at 0x0000.
+        let mut cpu = DCPU16::new(&[0x1234]);
+        cpu.ram_mut()[0xffff] = 0x7c01;
+        cpu.program_counter = 0xffff;
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x1234);
+        assert_eq!(cpu.program_counter, 0x0001);
+    }
+
+    #[test]
+    fn address_offset_wraps_around() {
+        // SET [0xFFFE+J], B
+        // SET C, [0xFFFE+J]
+        let program = [0x0571, 0xFFFE, 0x5c21, 0xFFFE, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_register(Register::J, 0x12);
+        cpu.set_register(Register::B, 0x1234);
+
+        cpu.step();
+        assert_eq!(cpu.ram()[0x0010], 0x1234);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::C), 0x1234);
+    }
+
+    #[test]
+    fn program_is_loaded_into_ram() {
+        let cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        assert_eq!(cpu.ram()[..SAMPLE_PROGRAM.len()], SAMPLE_PROGRAM);
+    }
+
+    #[test]
+    fn self_modifying_code_works() {
+        // SET [0x0003], 0x8811 ; replaces the next instruction with SET B, 0x02
+        // SET A, 0x01
+        let program = [0x7de1, 0x0003, 0x8811, 0x8401, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+
+        cpu.step();
+        assert_eq!(cpu.ram()[0x0003], 0x8811);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x0000);
+        assert_eq!(cpu.register(Register::B), 0x0002);
+    }
+
+    #[test]
+    fn interrupt_handler_works() {
+        // IAS 0x04
+        // INT 0x07
+        // SET B, 0x01
+        // SET PC, 0x03
+        // :handler SET C, A
+        // RFI 0x00
+        let program = [0x90a0, 0x9c80, 0x8411, 0x8dc1, 0x0021, 0x80b0];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_register(Register::A, 0x1234);
+
+        cpu.step();
+        assert_eq!(cpu.interrupt_address, 0x0004);
+
+        // The interrupt is queued and handled before the next instruction.
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0002);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::C), 0x0007);
+        assert_eq!(cpu.register(Register::A), 0x0007);
+        assert_eq!(cpu.stack_pointer, 0xfffd);
+        assert_eq!(cpu.ram()[0xfffe], 0x0002);
+        assert_eq!(cpu.ram()[0xfffd], 0x1234);
+        assert_eq!(cpu.program_counter, 0x0005);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x1234);
+        assert_eq!(cpu.stack_pointer, 0xffff);
+        assert_eq!(cpu.program_counter, 0x0002);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::B), 0x0001);
+    }
+
+    #[test]
+    fn interrupts_without_handler_are_ignored() {
+        // SET B, 0x01
+        // SET B, 0x02
+        let program = [0x8411, 0x8811, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.trigger_interrupt(0x0007);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0001);
+        assert_eq!(cpu.stack_pointer, STACK_POINTER_INIT as Word);
+    }
+
+    #[test]
+    fn interrupt_queueing_works() {
+        // IAQ 0x01
+        // SET B, 0x01
+        // IAQ 0x00
+        // SET B, 0x02
+        let program = [0x84c0, 0x8411, 0x80c0, 0x8811, 0x0000];
+        let mut cpu = DCPU16::new(&program);
+        cpu.interrupt_address = 0x1000;
+        cpu.set_ram(0x1000, 0x8811);
+
+        cpu.step();
+        cpu.trigger_interrupt(0x0007);
+
+        // The interrupt stays queued while queueing is enabled.
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0003);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x0007);
+        assert_eq!(cpu.program_counter, 0x1001);
+        assert_eq!(cpu.ram()[0xfffe], 0x0003);
+    }
+
+    #[test]
+    fn high_water_mark_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        assert_eq!(cpu.high_water_mark(), 0x0000);
+
+        // The loop fills 0x2001..=0x200A, the stack is not considered.
+        cpu.run();
+        assert_eq!(cpu.high_water_mark(), 0x200A);
+
+        cpu.reset();
+        assert_eq!(cpu.high_water_mark(), 0x0000);
+    }
+
+    #[test]
+    fn hexdump_range_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.run();
+
+        assert_eq!(
+            cpu.hexdump_range(0x1000, 0x1010, 6),
+            "1000: 0020 0000 0000 0000 0000 0000\n\
+             1006: 0000 0000 0000 0000 0000 0000\n\
+             100C: 0000 0000 0000 0000          \n"
+        );
+        assert_eq!(cpu.hexdump_range(0x1000, 0x1000, 6), "");
+        assert_eq!(cpu.hexdump_range(0x1010, 0x1000, 6), "");
+        assert_eq!(
+            cpu.hexdump_range(0x0000, 0x0008, 8),
+            cpu.hexdump_program(8)[..46]
+        );
+    }
+
+    #[test]
+    fn hexdump_ram_compact_works() {
+        let mut cpu = DCPU16::with_ram_words(&[0x7c01, 0x0030], 32);
+        cpu.set_ram(0x0011, 0x1234);
+
+        assert_eq!(
+            cpu.hexdump_ram_compact(4),
+            "0000: 7C01 0030 0000 0000\n\
+             0004: 0000 0000 0000 0000\n\
+             *\n\
+             0010: 0000 1234 0000 0000\n\
+             0014: 0000 0000 0000 0000\n\
+             *\n\
+             001C: 0000 0000 0000 0000\n"
+        );
+
+        // Single zero rows are kept.
+        cpu.set_ram(0x0008, 0x0001);
+        assert!(cpu
+            .hexdump_ram_compact(4)
+            .starts_with("0000: 7C01 0030 0000 0000\n0004: 0000 0000 0000 0000\n0008: 0001"));
+    }
+
+    #[test]
+    fn hexdump_ram_compact_keeps_partial_last_row() {
+        let mut cpu = DCPU16::with_ram_words(&[], 16);
+        cpu.set_ram(0x000F, 0xABCD);
+
+        assert_eq!(
+            cpu.hexdump_ram_compact(3),
+            "0000: 0000 0000 0000\n\
+             *\n\
+             000F: ABCD          \n"
+        );
+    }
+
+    #[test]
+    fn ram_mut_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.ram_mut()[0x1000] = 0x1234;
+        assert_eq!(cpu.ram()[0x1000], 0x1234);
     }
 }
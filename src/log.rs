@@ -0,0 +1,50 @@
+//! Logging macros forwarding to `tracing` if the `tracing` feature is enabled.
+//!
+//! Without the feature, the macros only type-check their arguments and expand to nothing,
+//! so that the emulator can be used without the dependency.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, info, trace, warn};
+
+// The macros are renamed on export, since `warn` is ambiguous with the built-in attribute.
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    macro_rules! disabled_debug {
+        ($($arg:tt)*) => {
+            if false {
+                let _ = format_args!($($arg)*);
+            }
+        };
+    }
+
+    macro_rules! disabled_info {
+        ($($arg:tt)*) => {
+            if false {
+                let _ = format_args!($($arg)*);
+            }
+        };
+    }
+
+    macro_rules! disabled_trace {
+        ($($arg:tt)*) => {
+            if false {
+                let _ = format_args!($($arg)*);
+            }
+        };
+    }
+
+    macro_rules! disabled_warn {
+        ($($arg:tt)*) => {
+            if false {
+                let _ = format_args!($($arg)*);
+            }
+        };
+    }
+
+    pub(crate) use {disabled_debug, disabled_info, disabled_trace, disabled_warn};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::{
+    disabled_debug as debug, disabled_info as info, disabled_trace as trace, disabled_warn as warn,
+};
@@ -0,0 +1,158 @@
+use crate::log::debug;
+use crate::{Word, DCPU16};
+use std::ops::{Deref, DerefMut, Range};
+
+/// A memory backend, e.g. the RAM or a memory-mapped device such as a framebuffer.
+pub trait Memory {
+    /// Reads the word at the specified address.
+    fn read(&self, addr: Word) -> Word;
+
+    /// Writes the word to the specified address.
+    fn write(&mut self, addr: Word, value: Word);
+}
+
+/// The RAM; addresses beyond its length wrap around.
+impl Memory for [Word] {
+    fn read(&self, addr: Word) -> Word {
+        self[addr as usize % self.len()]
+    }
+
+    fn write(&mut self, addr: Word, value: Word) {
+        let index = addr as usize % self.len();
+        self[index] = value;
+    }
+}
+
+/// The RAM of a CPU, either owned or borrowed from the caller.
+pub(crate) enum Ram<'a> {
+    /// RAM allocated by the CPU.
+    Owned(Box<[Word]>),
+    /// A caller-provided buffer.
+    Borrowed(&'a mut [Word]),
+}
+
+impl<'a> Ram<'a> {
+    /// Allocates the specified number of zeroed words.
+    pub(crate) fn zeroed(words: usize) -> Self {
+        Self::Owned(vec![0; words].into_boxed_slice())
+    }
+}
+
+impl<'a> Deref for Ram<'a> {
+    type Target = [Word];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Owned(ram) => ram,
+            Self::Borrowed(ram) => ram,
+        }
+    }
+}
+
+impl<'a> DerefMut for Ram<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Owned(ram) => ram,
+            Self::Borrowed(ram) => ram,
+        }
+    }
+}
+
+/// A memory backend handling a range of addresses instead of the RAM.
+pub(crate) struct MemoryMapping {
+    /// The mapped addresses.
+    range: Range<Word>,
+    /// The backend handling the addresses.
+    memory: Box<dyn Memory>,
+}
+
+impl<'p> DCPU16<'p> {
+    /// Maps the range of addresses to the memory backend, e.g. to intercept accesses
+    /// of a memory-mapped device.
+    ///
+    /// The backend receives the absolute addresses. Operands accessing mapped addresses are
+    /// handled by the backend instead of the RAM; instructions are always fetched from RAM.
+    /// If ranges overlap, the mapping added first takes precedence.
+    pub fn map_memory(&mut self, range: Range<Word>, memory: Box<dyn Memory>) {
+        debug!(
+            "Mapping memory {start:04X}..{end:04X}",
+            start = range.start,
+            end = range.end
+        );
+        self.memory_maps.push(MemoryMapping { range, memory });
+    }
+
+    /// Reads the word at the specified address from the responsible memory backend.
+    pub(crate) fn read_memory(&self, addr: Word) -> Word {
+        match self
+            .memory_maps
+            .iter()
+            .find(|map| map.range.contains(&addr))
+        {
+            Some(map) => map.memory.read(addr),
+            None => self.ram.read(addr),
+        }
+    }
+
+    /// Writes the word to the specified address of the responsible memory backend.
+    pub(crate) fn write_memory(&mut self, addr: Word, value: Word) {
+        match self
+            .memory_maps
+            .iter_mut()
+            .find(|map| map.range.contains(&addr))
+        {
+            Some(map) => map.memory.write(addr, value),
+            None => self.ram.write(addr, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Register;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A framebuffer recording all writes.
+    struct LoggingMemory {
+        writes: Rc<RefCell<Vec<(Word, Word)>>>,
+    }
+
+    impl Memory for LoggingMemory {
+        fn read(&self, addr: Word) -> Word {
+            addr
+        }
+
+        fn write(&mut self, addr: Word, value: Word) {
+            self.writes.borrow_mut().push((addr, value));
+        }
+    }
+
+    #[test]
+    fn mapped_memory_works() {
+        // SET [0x8000], 0x1F
+        // SET A, [0x8010]
+        // SET [0x1000], 0x1F
+        let program = [0xfde1, 0x8000, 0x7801, 0x8010, 0xfde1, 0x1000];
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = DCPU16::new(&program);
+        cpu.map_memory(
+            0x8000..0x8400,
+            Box::new(LoggingMemory {
+                writes: writes.clone(),
+            }),
+        );
+
+        cpu.step();
+        assert_eq!(*writes.borrow(), vec![(0x8000, 0x001F)]);
+        assert_eq!(cpu.ram()[0x8000], 0x0000);
+
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x8010);
+
+        cpu.step();
+        assert_eq!(writes.borrow().len(), 1);
+        assert_eq!(cpu.ram()[0x1000], 0x001F);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{Register, Word};
+
+/// The reason why the execution of a program stopped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// A jump to the same instruction was detected.
+    CrashLoop {
+        /// The address of the jump.
+        pc: Word,
+    },
+    /// The program halted intentionally.
+    Halted,
+    /// The program counter moved past the end of the program.
+    EndOfProgram,
+    /// A reserved instruction was encountered, e.g. when running into zeroed RAM.
+    ReservedInstruction {
+        /// The address of the reserved instruction.
+        pc: Word,
+    },
+    /// A literal was assigned to under [`LiteralWritePolicy::Error`].
+    LiteralWrite {
+        /// The address of the assigning instruction.
+        pc: Word,
+    },
+    /// The maximum number of steps was executed.
+    StepLimitReached,
+    /// The next instruction would have exceeded the cycle budget.
+    CycleLimitReached,
+    /// The program counter reached a breakpoint at the specified address.
+    Breakpoint(Word),
+    /// A watched RAM address was written.
+    Watchpoint {
+        /// The address that was written.
+        addr: Word,
+        /// The value before the write.
+        old: Word,
+        /// The value after the write.
+        new: Word,
+    },
+    /// A watched register was written.
+    RegisterWrite {
+        /// The register that was written.
+        register: Register,
+        /// The value before the write.
+        old: Word,
+        /// The value after the write.
+        new: Word,
+    },
+}
+
+/// The result of executing a single step.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepResult {
+    /// The instruction was executed.
+    Executed,
+    /// The instruction was skipped due to a failed test.
+    Skipped,
+    /// A jump to the same instruction was detected.
+    CrashLoop,
+    /// The program halted intentionally.
+    Halted,
+    /// The program counter moved past the end of the program.
+    EndOfProgram,
+    /// A reserved instruction was encountered and not executed.
+    ReservedInstruction,
+    /// The instruction was executed, but assigned to a literal under [`LiteralWritePolicy::Error`].
+    LiteralWrite,
+}
+
+/// The handling of assignments to a literal, e.g. `SET 0x5, A`.
+///
+/// Such assignments fail silently per the specification, but are usually mistakes,
+/// e.g. swapped operands.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LiteralWritePolicy {
+    /// The assignment is ignored.
+    #[default]
+    Ignore,
+    /// The assignment is ignored and a warning is logged.
+    Warn,
+    /// The assignment is ignored and execution stops after the instruction.
+    Error,
+}
+
+impl StepResult {
+    /// Gets the outcome of a run that ends with this step, if execution cannot continue.
+    ///
+    /// The `pc` is the address of the instruction of the step.
+    pub(crate) fn outcome(self, pc: Word) -> Option<RunOutcome> {
+        match self {
+            Self::Executed | Self::Skipped => None,
+            Self::CrashLoop => Some(RunOutcome::CrashLoop { pc }),
+            Self::Halted => Some(RunOutcome::Halted),
+            Self::EndOfProgram => Some(RunOutcome::EndOfProgram),
+            Self::ReservedInstruction => Some(RunOutcome::ReservedInstruction { pc }),
+            Self::LiteralWrite => Some(RunOutcome::LiteralWrite { pc }),
+        }
+    }
+}
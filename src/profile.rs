@@ -0,0 +1,88 @@
+use crate::instruction_word::InstructionWord;
+use crate::DCPU16;
+use std::collections::HashMap;
+
+/// The number of executions per opcode, e.g. to find the hotspots of a workload.
+///
+/// Opcodes are identified by their mnemonic, e.g. `SET`. Skipped instructions are not counted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OpcodeProfile {
+    counts: HashMap<&'static str, u64>,
+}
+
+impl OpcodeProfile {
+    /// Gets the number of executions of the opcode with the specified mnemonic.
+    pub fn count(&self, mnemonic: &str) -> u64 {
+        self.counts.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    /// Gets the total number of executions of all opcodes.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Gets the most executed opcode and its number of executions, if any.
+    ///
+    /// Ties are resolved in favor of the alphabetically first mnemonic.
+    pub fn most_executed(&self) -> Option<(&'static str, u64)> {
+        self.iter()
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+    }
+
+    /// Iterates the executed opcodes along with their number of executions, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        self.counts
+            .iter()
+            .map(|(&mnemonic, &count)| (mnemonic, count))
+    }
+
+    /// Counts an execution of the instruction.
+    fn record(&mut self, instruction: &InstructionWord) {
+        *self.counts.entry(instruction.mnemonic()).or_insert(0) += 1;
+    }
+}
+
+impl<'p> DCPU16<'p> {
+    /// Starts counting the executions per opcode, discarding any previous counts.
+    pub fn enable_opcode_profile(&mut self) {
+        self.opcode_profile = OpcodeProfile::default();
+        self.profiling_opcodes = true;
+    }
+
+    /// Gets the executions per opcode counted since profiling was enabled or the CPU was reset.
+    pub fn opcode_profile(&self) -> &OpcodeProfile {
+        &self.opcode_profile
+    }
+
+    /// Counts an execution of the instruction if profiling is enabled.
+    pub(crate) fn profile_opcode(&mut self, instruction: &InstructionWord) {
+        if self.profiling_opcodes {
+            self.opcode_profile.record(instruction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SAMPLE_PROGRAM;
+
+    #[test]
+    fn opcode_profile_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.run();
+        assert_eq!(cpu.opcode_profile().total(), 0);
+
+        cpu.reset();
+        cpu.enable_opcode_profile();
+        cpu.run();
+
+        let profile = cpu.opcode_profile();
+        assert_eq!(profile.most_executed(), Some(("SET", 27)));
+        assert_eq!(profile.count("SUB"), 11);
+        assert_eq!(profile.count("IFN"), 11);
+        assert_eq!(profile.count("JSR"), 1);
+        assert_eq!(profile.count("MUL"), 0);
+        assert_eq!(profile.total(), cpu.executed_count);
+    }
+}
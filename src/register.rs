@@ -1,7 +1,9 @@
 use crate::Word;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 /// Identifier for a CPU register.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Register {
     A = 0,
     B = 1,
@@ -30,6 +32,53 @@ impl From<Word> for Register {
     }
 }
 
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Register::A => "A",
+            Register::B => "B",
+            Register::C => "C",
+            Register::X => "X",
+            Register::Y => "Y",
+            Register::Z => "Z",
+            Register::I => "I",
+            Register::J => "J",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The error returned when parsing an unknown register name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRegisterError(String);
+
+impl Display for ParseRegisterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown register '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseRegisterError {}
+
+impl FromStr for Register {
+    type Err = ParseRegisterError;
+
+    /// Parses a register name, e.g. `A` or `j`, ignoring the case.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_uppercase().as_str() {
+            "A" => Ok(Register::A),
+            "B" => Ok(Register::B),
+            "C" => Ok(Register::C),
+            "X" => Ok(Register::X),
+            "Y" => Ok(Register::Y),
+            "Z" => Ok(Register::Z),
+            "I" => Ok(Register::I),
+            "J" => Ok(Register::J),
+            _ => Err(ParseRegisterError(String::from(name))),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -39,4 +88,27 @@ mod tests {
         assert_eq!(Register::from(0x00), Register::A);
         assert_eq!(Register::from(0x07), Register::J);
     }
+
+    #[test]
+    fn display_works() {
+        assert_eq!(format!("{}", Register::X), "X");
+        assert_eq!(Register::J.to_string(), "J");
+    }
+
+    #[test]
+    fn from_str_works() {
+        for value in 0..=Register::J as Word {
+            let register = Register::from(value);
+            assert_eq!(register.to_string().parse(), Ok(register));
+        }
+        assert_eq!("x".parse(), Ok(Register::X));
+        assert_eq!(
+            "PC".parse::<Register>(),
+            Err(ParseRegisterError(String::from("PC")))
+        );
+        assert_eq!(
+            "".parse::<Register>().unwrap_err().to_string(),
+            "Unknown register ''"
+        );
+    }
 }
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use crate::{Word, DCPU16, NUM_REGISTERS};
+
+/// A copy of the complete machine state of a [`DCPU16`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct CpuSnapshot {
+    /// RAM.
+    pub(crate) ram: Box<[Word]>,
+    /// Registers.
+    pub(crate) registers: [Word; NUM_REGISTERS],
+    /// Program counter.
+    pub(crate) program_counter: Word,
+    /// Stack pointer.
+    pub(crate) stack_pointer: Word,
+    /// Overflow.
+    pub(crate) overflow: Word,
+    /// Interrupt address.
+    interrupt_address: Word,
+    /// Indicates whether the next instruction should be skipped.
+    skip_next_intruction: bool,
+    /// The number of cycles consumed so far.
+    cycles: u64,
+    /// The number of instructions executed so far.
+    executed_count: u64,
+    /// The number of instructions skipped so far.
+    skipped_count: u64,
+    /// Interrupt messages waiting to be handled.
+    interrupt_queue: VecDeque<Word>,
+    /// Indicates whether interrupts are queued instead of triggered.
+    interrupt_queueing: bool,
+    /// Indicates whether the CPU executed a `HLT` instruction.
+    halted: bool,
+}
+
+impl<'p> DCPU16<'p> {
+    /// Captures the complete machine state.
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            ram: Box::from(&self.ram[..]),
+            registers: self.registers,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            overflow: self.overflow,
+            interrupt_address: self.interrupt_address,
+            skip_next_intruction: self.skip_next_intruction,
+            cycles: self.cycles,
+            executed_count: self.executed_count,
+            skipped_count: self.skipped_count,
+            interrupt_queue: self.interrupt_queue.clone(),
+            interrupt_queueing: self.interrupt_queueing,
+            halted: self.halted,
+        }
+    }
+
+    /// Restores the machine state from a snapshot.
+    ///
+    /// The program the CPU was constructed with and the connected devices are kept.
+    /// The snapshot must have been captured from a CPU with the same RAM size.
+    pub fn restore(&mut self, snapshot: &CpuSnapshot) {
+        assert_eq!(self.ram.len(), snapshot.ram.len(), "RAM size mismatch");
+        self.ram.copy_from_slice(snapshot.ram.as_ref());
+        self.registers = snapshot.registers;
+        self.program_counter = snapshot.program_counter;
+        self.previous_program_counter = snapshot.program_counter;
+        self.stack_pointer = snapshot.stack_pointer;
+        self.overflow = snapshot.overflow;
+        self.interrupt_address = snapshot.interrupt_address;
+        self.skip_next_intruction = snapshot.skip_next_intruction;
+        self.cycles = snapshot.cycles;
+        self.executed_count = snapshot.executed_count;
+        self.skipped_count = snapshot.skipped_count;
+        self.interrupt_queue.clone_from(&snapshot.interrupt_queue);
+        self.interrupt_queueing = snapshot.interrupt_queueing;
+        self.halted = snapshot.halted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Register, StepResult};
+
+    #[test]
+    fn snapshot_and_restore_works() {
+        // SET A, 0x30
+        // SET [0x1000], 0x20
+        // SUB A, [0x1000]
+        let program = [
+            0x7c01, 0x0030, 0x7de1, 0x1000, 0x0020, 0x7803, 0x1000, 0x0000,
+        ];
+        let mut cpu = DCPU16::new(&program);
+
+        cpu.step();
+        let snapshot = cpu.snapshot();
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x10);
+        assert_eq!(cpu.ram()[0x1000], 0x20);
+
+        cpu.restore(&snapshot);
+        assert_eq!(cpu.register(Register::A), 0x30);
+        assert_eq!(cpu.ram()[0x1000], 0x00);
+        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.cycles(), 2);
+        assert_eq!(cpu.executed_count(), 1);
+        assert!(cpu.snapshot() == snapshot);
+
+        // Execution resumes from the restored state.
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.register(Register::A), 0x10);
+    }
+
+    #[test]
+    fn restore_across_halt_works() {
+        // SET A, 0x01
+        // HLT
+        let program = [0x8401, 0x83d0];
+        let mut cpu = DCPU16::new(&program);
+
+        let snapshot = cpu.snapshot();
+        cpu.step();
+        assert_eq!(cpu.step(), StepResult::Halted);
+        assert_eq!(cpu.step(), StepResult::Halted);
+
+        cpu.restore(&snapshot);
+        assert_eq!(cpu.step(), StepResult::Executed);
+        assert_eq!(cpu.register(Register::A), 0x01);
+        assert_eq!(cpu.step(), StepResult::Halted);
+    }
+}
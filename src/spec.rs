@@ -0,0 +1,22 @@
+/// The revision of the DCPU-16 specification a program is written against.
+///
+/// The overflow register is called `O` in revision 1.1 and `EX` in revision 1.7, but both
+/// revisions specify the same overflow conventions, hence the emulator behaves identically for
+/// either version:
+///
+/// - `ADD` and `SUB` write `0x0001` respectively `0xffff` on carry, and `0x0000` otherwise.
+/// - `MUL` writes `((a*b)>>16)&0xffff`.
+/// - `DIV` writes `((a<<16)/b)&0xffff`, and `0x0000` if `b` is zero.
+/// - `SHL` writes `((a<<b)>>16)&0xffff` and `SHR` writes `((a<<16)>>b)&0xffff`.
+///
+/// In particular, all of these instructions write the overflow register even if nothing
+/// carries, i.e. they clear it. Revision 1.7 swaps the operand names (`b` is the target), which
+/// does not affect the results. All other instructions leave the overflow register unchanged.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SpecVersion {
+    /// Revision 1.1, naming the overflow register `O`.
+    #[default]
+    V1_1,
+    /// Revision 1.7, naming the overflow register `EX`.
+    V1_7,
+}
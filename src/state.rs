@@ -0,0 +1,115 @@
+use crate::{Word, DCPU16, NUM_REGISTERS};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A serializable view of the registers and RAM of a [`DCPU16`].
+///
+/// Unlike a [`CpuSnapshot`](crate::CpuSnapshot), the RAM is run-length encoded
+/// in order to keep the mostly empty memory compact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CpuState {
+    /// Registers.
+    pub registers: [Word; NUM_REGISTERS],
+    /// Program counter.
+    pub program_counter: Word,
+    /// Stack pointer.
+    pub stack_pointer: Word,
+    /// Overflow.
+    pub overflow: Word,
+    /// The run-length encoded RAM, starting at address `0x0000`.
+    pub ram: Vec<RamRun>,
+}
+
+/// A run of consecutive RAM cells sharing the same value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RamRun {
+    /// The value of the cells.
+    pub value: Word,
+    /// The number of cells.
+    pub count: u32,
+}
+
+impl<'p> DCPU16<'p> {
+    /// Exports the registers and RAM.
+    pub fn export_state(&self) -> CpuState {
+        let mut ram: Vec<RamRun> = Vec::new();
+        for &value in self.ram.iter() {
+            match ram.last_mut() {
+                Some(run) if run.value == value => run.count += 1,
+                _ => ram.push(RamRun { value, count: 1 }),
+            }
+        }
+
+        CpuState {
+            registers: self.registers,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            overflow: self.overflow,
+            ram,
+        }
+    }
+
+    /// Imports the registers and RAM.
+    ///
+    /// Cells not covered by the RAM runs are set to zero, runs exceeding the RAM are truncated.
+    pub fn import_state(&mut self, state: &CpuState) {
+        let cells = state
+            .ram
+            .iter()
+            .flat_map(|run| std::iter::repeat_n(run.value, run.count as usize))
+            .chain(std::iter::repeat(0));
+        for (cell, value) in self.ram.iter_mut().zip(cells) {
+            *cell = value;
+        }
+
+        self.registers = state.registers;
+        self.program_counter = state.program_counter;
+        self.previous_program_counter = state.program_counter;
+        self.stack_pointer = state.stack_pointer;
+        self.overflow = state.overflow;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SAMPLE_PROGRAM;
+    use crate::NUM_RAM_WORDS;
+
+    #[test]
+    fn export_and_import_state_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.run();
+        cpu.set_ram(0xffff, 0x1234);
+
+        let state = cpu.export_state();
+        let total: u32 = state.ram.iter().map(|run| run.count).sum();
+        assert_eq!(total as usize, NUM_RAM_WORDS);
+        assert!(state.ram.len() < 64);
+
+        let mut other = DCPU16::new(&[]);
+        other.import_state(&state);
+        assert_eq!(other.export_state(), state);
+        assert_eq!(other.ram()[..], cpu.ram()[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn state_json_round_trip_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.run();
+
+        let state = cpu.export_state();
+        let json = serde_json::to_string(&state).unwrap();
+        let parsed: CpuState = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, state);
+
+        let mut other = DCPU16::new(&[]);
+        other.import_state(&parsed);
+        assert_eq!(other.ram()[..], cpu.ram()[..]);
+        assert_eq!(other.program_counter, cpu.program_counter);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::disassemble::Disassemble;
+use crate::disassembler::DisassemblyOptions;
+use crate::instruction::InstructionWithOperands;
+use crate::{Word, DCPU16, NUM_REGISTERS};
+use std::fmt::{Display, Formatter};
+
+/// A callback invoked once per executed or skipped instruction.
+pub type TraceHook = Box<dyn FnMut(&TraceEvent)>;
+
+/// Describes a single step of the CPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// The program counter before the instruction was executed.
+    pub program_counter: Word,
+    /// The raw instruction word.
+    pub instruction: Word,
+    /// The disassembled instruction, e.g. `SET A, 0x30`.
+    pub mnemonic: String,
+    /// Indicates whether the instruction was skipped due to a failed test.
+    pub skipped: bool,
+    /// The number of cycles consumed after the instruction.
+    pub cycles: u64,
+}
+
+/// A recorded step of the CPU along with the resulting state, e.g. for golden-file tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// The program counter before the instruction was executed.
+    pub program_counter: Word,
+    /// The disassembled instruction, e.g. `SET A, 0x30`.
+    pub mnemonic: String,
+    /// Indicates whether the instruction was skipped due to a failed test.
+    pub skipped: bool,
+    /// The registers after the step.
+    pub registers: [Word; NUM_REGISTERS],
+    /// The overflow after the step.
+    pub overflow: Word,
+}
+
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, x, y, z, i, j] = self.registers;
+        write!(
+            f,
+            "{pc:04X}: {mnemonic:<24}{skipped} ; A={a:04X} B={b:04X} C={c:04X} X={x:04X} Y={y:04X} Z={z:04X} I={i:04X} J={j:04X} O={o:04X}",
+            pc = self.program_counter,
+            mnemonic = self.mnemonic,
+            skipped = if self.skipped { "*" } else { " " },
+            a = a,
+            b = b,
+            c = c,
+            x = x,
+            y = y,
+            z = z,
+            i = i,
+            j = j,
+            o = self.overflow
+        )
+    }
+}
+
+impl<'p> DCPU16<'p> {
+    /// Starts recording a [`TraceRecord`] per [`step()`](DCPU16::step),
+    /// discarding any previous recording.
+    pub fn enable_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// Takes the steps recorded so far; recording continues if it is enabled.
+    pub fn take_recording(&mut self) -> Vec<TraceRecord> {
+        self.recording
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Installs a hook that is called once per [`step()`](DCPU16::step),
+    /// replacing any previously installed hook.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+
+    /// Removes the trace hook.
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    /// Sets the options used to disassemble the mnemonics of traced and recorded steps,
+    /// e.g. to render literals as signed decimals.
+    pub fn set_trace_options(&mut self, options: DisassemblyOptions) {
+        self.trace_options = options;
+    }
+
+    /// Captures the parts of the trace event that are only available before execution.
+    pub(crate) fn begin_trace_event(
+        &self,
+        instruction: &InstructionWithOperands,
+    ) -> Option<TraceEvent> {
+        if self.trace_hook.is_none() && self.recording.is_none() {
+            return None;
+        }
+
+        Some(TraceEvent {
+            program_counter: self.previous_program_counter,
+            instruction: self.ram[self.ram_index(self.previous_program_counter)],
+            mnemonic: instruction.disassemble_with(&self.trace_options),
+            skipped: false,
+            cycles: 0,
+        })
+    }
+
+    /// Completes the trace event, passes it to the trace hook and records the step.
+    pub(crate) fn end_trace_event(&mut self, event: Option<TraceEvent>, skipped: bool) {
+        let mut event = match event {
+            Some(event) => event,
+            None => return,
+        };
+
+        event.skipped = skipped;
+        event.cycles = self.cycles;
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(&event);
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(TraceRecord {
+                program_counter: event.program_counter,
+                mnemonic: event.mnemonic,
+                skipped,
+                registers: self.registers,
+                overflow: self.overflow,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::LiteralFormat;
+    use crate::fixtures::SAMPLE_PROGRAM;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn trace_hook_works() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+
+        let recorded = events.clone();
+        cpu.set_trace_hook(Box::new(move |event| {
+            recorded.borrow_mut().push(event.clone())
+        }));
+        cpu.run();
+
+        let events = events.borrow();
+        let skipped = events.iter().filter(|event| event.skipped).count();
+        assert_eq!(skipped, 2);
+        assert_eq!(events.len() - skipped, 51);
+
+        assert_eq!(
+            events[0],
+            TraceEvent {
+                program_counter: 0x0000,
+                instruction: 0x7c01,
+                mnemonic: String::from("SET A, 0x30"),
+                skipped: false,
+                cycles: 2,
+            }
+        );
+        assert_eq!(events.last().unwrap().program_counter, 0x001A);
+        assert_eq!(events.last().unwrap().cycles, cpu.cycles());
+    }
+
+    #[test]
+    fn recording_works() {
+        let mut cpu = DCPU16::new(&SAMPLE_PROGRAM);
+        cpu.step();
+        assert!(cpu.take_recording().is_empty());
+
+        cpu.enable_recording();
+        cpu.run();
+        let recording = cpu.take_recording();
+        assert_eq!(recording.len(), 52);
+
+        let steps: Vec<(Word, &str, bool)> = recording
+            .iter()
+            .take(4)
+            .map(|record| {
+                (
+                    record.program_counter,
+                    record.mnemonic.as_str(),
+                    record.skipped,
+                )
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (0x0002, "SET [0x1000], 0x20", false),
+                (0x0005, "SUB A, [0x1000]", false),
+                (0x0007, "IFN A, 0x10", false),
+                (0x0008, "SET PC, 0x1A", true),
+            ]
+        );
+        assert_eq!(recording[1].registers[0], 0x0010);
+        assert_eq!(
+            recording[1].to_string(),
+            "0005: SUB A, [0x1000]           ; A=0010 B=0000 C=0000 X=0000 Y=0000 Z=0000 I=0000 J=0000 O=0000"
+        );
+
+        // Recording continues after the recording was taken.
+        cpu.reset();
+        cpu.step();
+        assert_eq!(cpu.take_recording().len(), 1);
+    }
+
+    #[test]
+    fn trace_options_work() {
+        // SET A, 0xFFFF
+        let program = [0x7c01, 0xffff];
+        let mut cpu = DCPU16::new(&program);
+        cpu.set_trace_options(DisassemblyOptions {
+            literal_format: LiteralFormat::SignedDecimal,
+            ..DisassemblyOptions::default()
+        });
+
+        cpu.enable_recording();
+        cpu.step();
+        assert_eq!(cpu.take_recording()[0].mnemonic, "SET A, -1");
+    }
+}
@@ -0,0 +1,128 @@
+mod private {
+    /// Prevents implementations of [`CpuWord`](super::CpuWord) outside of this crate.
+    pub trait Sealed {}
+
+    impl Sealed for u16 {}
+}
+
+/// The machine word of a CPU, decoupling the arithmetic of the instructions from the
+/// word width.
+///
+/// Each operation returns the result along with the value of the overflow register `O`.
+/// The trait is sealed; `u16` is the only implementation for now.
+pub trait CpuWord: private::Sealed + Copy + Eq + Ord {
+    /// Adds `rhs`; the overflow is `1` if the addition carried.
+    fn add_with_overflow(self, rhs: Self) -> (Self, Self);
+
+    /// Subtracts `rhs`; the overflow is all ones if the subtraction borrowed.
+    fn sub_with_overflow(self, rhs: Self) -> (Self, Self);
+
+    /// Multiplies by `rhs`; the overflow holds the upper word of the product.
+    fn mul_with_overflow(self, rhs: Self) -> (Self, Self);
+
+    /// Divides by `rhs`; the overflow holds the fractional part of the quotient.
+    /// Division by zero yields zero for both.
+    fn div_with_overflow(self, rhs: Self) -> (Self, Self);
+
+    /// Multiplies by `rhs`, treating both as signed; the overflow holds the upper
+    /// word of the product.
+    fn mli_with_overflow(self, rhs: Self) -> (Self, Self);
+
+    /// Divides by `rhs`, treating both as signed and rounding towards zero; the overflow
+    /// holds the fractional part of the quotient. Division by zero yields zero for both.
+    fn dvi_with_overflow(self, rhs: Self) -> (Self, Self);
+
+    /// Shifts left by `rhs` bits; the overflow holds the bits shifted out.
+    fn shl_with_overflow(self, rhs: Self) -> (Self, Self);
+
+    /// Shifts right by `rhs` bits; the overflow holds the bits shifted out.
+    fn shr_with_overflow(self, rhs: Self) -> (Self, Self);
+}
+
+impl CpuWord for u16 {
+    fn add_with_overflow(self, rhs: Self) -> (Self, Self) {
+        let (result, overflow) = self.overflowing_add(rhs);
+        (result, if overflow { 0x0001 } else { 0x0 })
+    }
+
+    fn sub_with_overflow(self, rhs: Self) -> (Self, Self) {
+        let (result, overflow) = self.overflowing_sub(rhs);
+        (result, if overflow { 0xffff } else { 0x0 })
+    }
+
+    fn mul_with_overflow(self, rhs: Self) -> (Self, Self) {
+        let result = self.wrapping_mul(rhs);
+        let overflow = (((self as u32 * rhs as u32) >> 16) & 0xffff) as _;
+        (result, overflow)
+    }
+
+    fn div_with_overflow(self, rhs: Self) -> (Self, Self) {
+        if rhs == 0 {
+            return (0, 0);
+        }
+        let result = self.wrapping_div(rhs);
+        let overflow = ((((self as u32) << 16) / (rhs as u32)) & 0xffff) as _;
+        (result, overflow)
+    }
+
+    fn mli_with_overflow(self, rhs: Self) -> (Self, Self) {
+        let result = (self as i16 as i32) * (rhs as i16 as i32);
+        (result as u16, ((result >> 16) & 0xffff) as _)
+    }
+
+    fn dvi_with_overflow(self, rhs: Self) -> (Self, Self) {
+        if rhs == 0 {
+            return (0, 0);
+        }
+        let (lhs, rhs) = (self as i16 as i32, rhs as i16 as i32);
+        let overflow = ((lhs << 16).wrapping_div(rhs) & 0xffff) as _;
+        ((lhs / rhs) as u16, overflow)
+    }
+
+    fn shl_with_overflow(self, rhs: Self) -> (Self, Self) {
+        // Shifting by 32 bits or more moves all bits out, including the overflow.
+        let shifted = (self as u32).checked_shl(rhs as u32).unwrap_or(0);
+        (shifted as u16, ((shifted >> 16) & 0xffff) as u16)
+    }
+
+    fn shr_with_overflow(self, rhs: Self) -> (Self, Self) {
+        let result = (self as u32).checked_shr(rhs as u32).unwrap_or(0) as u16;
+        let shifted = ((self as u32) << 16).checked_shr(rhs as u32).unwrap_or(0);
+        (result, (shifted & 0xffff) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u16_arithmetic_works() {
+        assert_eq!(0xfffeu16.add_with_overflow(0x0003), (0x0001, 0x0001));
+        assert_eq!(0x0001u16.sub_with_overflow(0x0002), (0xffff, 0xffff));
+        assert_eq!(0x1234u16.mul_with_overflow(0x0100), (0x3400, 0x0012));
+        assert_eq!(0x0001u16.div_with_overflow(0x0004), (0x0000, 0x4000));
+        assert_eq!(0x0001u16.div_with_overflow(0x0000), (0x0000, 0x0000));
+        assert_eq!(0xfffeu16.mli_with_overflow(0x0003), (0xfffa, 0xffff));
+        assert_eq!(0xfff9u16.dvi_with_overflow(0x0002), (0xfffd, 0x8000));
+        assert_eq!(0x8001u16.shl_with_overflow(0x0004), (0x0010, 0x0008));
+        assert_eq!(0x8001u16.shr_with_overflow(0x0004), (0x0800, 0x1000));
+        assert_eq!(0x8001u16.shl_with_overflow(0x0030), (0x0000, 0x0000));
+    }
+
+    #[test]
+    fn large_shifts_work() {
+        // Bits are shifted out of the result and then out of the overflow word.
+        let cases = [
+            (16, (0x0000, 0x8001), (0x0000, 0x8001)),
+            (31, (0x0000, 0x8000), (0x0000, 0x0001)),
+            (32, (0x0000, 0x0000), (0x0000, 0x0000)),
+            (48, (0x0000, 0x0000), (0x0000, 0x0000)),
+            (0xffff, (0x0000, 0x0000), (0x0000, 0x0000)),
+        ];
+        for &(count, shl, shr) in cases.iter() {
+            assert_eq!(0x8001u16.shl_with_overflow(count), shl, "SHL by {}", count);
+            assert_eq!(0x8001u16.shr_with_overflow(count), shr, "SHR by {}", count);
+        }
+    }
+}